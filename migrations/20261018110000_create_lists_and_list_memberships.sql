@@ -0,0 +1,27 @@
+CREATE TABLE lists (
+    list_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    is_default BOOLEAN NOT NULL DEFAULT false,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id)
+);
+-- At most one list receives subscribers who do not pick a list.
+CREATE UNIQUE INDEX lists_single_default ON lists (is_default) WHERE is_default;
+
+CREATE TABLE list_memberships (
+    list_id uuid NOT NULL REFERENCES lists(list_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    status TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id, subscriber_id)
+);
+
+ALTER TABLE newsletter_issues ADD COLUMN list_ids uuid[] NOT NULL DEFAULT '{}';
+
+-- Existing deployments had a single implicit list: make it explicit.
+INSERT INTO lists (list_id, name, is_default)
+VALUES ('72195182-62cb-4dc6-ad1d-9dd66ad5cf76', 'Newsletter', true);
+
+INSERT INTO list_memberships (list_id, subscriber_id, status)
+SELECT '72195182-62cb-4dc6-ad1d-9dd66ad5cf76', id, COALESCE(status, 'pending_confirmation')
+FROM subscriptions;
@@ -0,0 +1,19 @@
+CREATE TABLE tags (
+    tag_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (tag_id)
+);
+
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    tag_id uuid NOT NULL REFERENCES tags(tag_id),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscriber_id, tag_id)
+);
+
+-- A segment narrows the chosen lists: recipients must carry at least one
+-- of the included tags (if any are given) and none of the excluded ones.
+ALTER TABLE newsletter_issues
+    ADD COLUMN include_tag_ids uuid[] NOT NULL DEFAULT '{}',
+    ADD COLUMN exclude_tag_ids uuid[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,5 @@
+-- Keyset pagination walks subscribers from the most recent one.
+CREATE INDEX subscriptions_subscribed_at_id ON subscriptions (subscribed_at DESC, id DESC);
+-- Case-insensitive prefix search on email and name.
+CREATE INDEX subscriptions_lower_email_prefix ON subscriptions (lower(email) text_pattern_ops);
+CREATE INDEX subscriptions_lower_name_prefix ON subscriptions (lower(name) text_pattern_ops);
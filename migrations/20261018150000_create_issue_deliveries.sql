@@ -0,0 +1,6 @@
+CREATE TABLE issue_deliveries (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues(newsletter_issue_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    delivered_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (newsletter_issue_id, subscriber_id)
+);
@@ -0,0 +1,5 @@
+CREATE TABLE erasure_tombstones (
+    email_hash TEXT NOT NULL,
+    erased_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (email_hash)
+);
@@ -0,0 +1,23 @@
+CREATE TABLE consent_events (
+    consent_event_id uuid NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    event TEXT NOT NULL,
+    source TEXT NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    consent_text_version TEXT NULL,
+    occurred_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (consent_event_id)
+);
+CREATE INDEX consent_events_subscriber_id ON consent_events (subscriber_id, occurred_at);
+
+-- Events are evidence: they can be erased together with the subscriber, never rewritten.
+CREATE FUNCTION reject_consent_event_update() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'consent_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_events_append_only
+    BEFORE UPDATE ON consent_events
+    FOR EACH ROW EXECUTE FUNCTION reject_consent_event_update();
@@ -0,0 +1 @@
+ALTER TABLE subscriptions ADD COLUMN signup_source TEXT NULL;
@@ -0,0 +1,13 @@
+-- The address duplicates are detected on, filled in by the application.
+ALTER TABLE subscriptions ADD COLUMN canonical_email TEXT NULL;
+-- Addresses that only differ in case were separate subscribers until now:
+-- the oldest of them takes the canonical form, the others are left without one.
+UPDATE subscriptions s
+SET canonical_email = lower(s.email)
+WHERE s.id = (
+    SELECT t.id FROM subscriptions t
+    WHERE lower(t.email) = lower(s.email)
+    ORDER BY t.subscribed_at, t.id
+    LIMIT 1
+);
+CREATE UNIQUE INDEX subscriptions_canonical_email_key ON subscriptions (canonical_email);
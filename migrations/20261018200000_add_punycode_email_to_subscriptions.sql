@@ -0,0 +1,5 @@
+-- `email` is the display form, with the domain in Unicode; this is the same
+-- address with the domain in punycode. They only differ for IDN domains.
+ALTER TABLE subscriptions ADD COLUMN punycode_email TEXT NULL;
+-- A domain of ASCII characters is already its own punycode.
+UPDATE subscriptions SET punycode_email = email WHERE split_part(email, '@', 2) ~ '^[\x01-\x7f]*$';
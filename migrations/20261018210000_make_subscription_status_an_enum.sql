@@ -0,0 +1,35 @@
+CREATE TYPE subscription_status AS ENUM (
+    'pending_confirmation',
+    'confirmed',
+    'unsubscribed',
+    'bounced',
+    'complained'
+);
+
+-- Rows from before statuses were recorded never confirmed their address.
+ALTER TABLE subscriptions
+    ALTER COLUMN status TYPE subscription_status
+        USING COALESCE(status, 'pending_confirmation')::subscription_status,
+    ALTER COLUMN status SET NOT NULL;
+ALTER TABLE list_memberships
+    ALTER COLUMN status TYPE subscription_status USING status::subscription_status;
+
+CREATE TABLE subscription_status_history (
+    subscription_status_change_id uuid NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    -- NULL when the subscriber was created.
+    from_status subscription_status NULL,
+    to_status subscription_status NOT NULL,
+    reason TEXT NOT NULL,
+    changed_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscription_status_change_id)
+);
+CREATE INDEX subscription_status_history_subscriber_id
+    ON subscription_status_history (subscriber_id, changed_at);
+
+-- How existing subscribers got to their status is unknown: start their history there.
+INSERT INTO subscription_status_history (
+    subscription_status_change_id, subscriber_id, from_status, to_status, reason, changed_at
+)
+SELECT gen_random_uuid(), id, NULL, status, 'backfill', subscribed_at
+FROM subscriptions;
@@ -0,0 +1,24 @@
+-- A single row: the email sent to every subscriber once they confirm.
+CREATE TABLE welcome_email (
+    singleton BOOLEAN NOT NULL DEFAULT true CHECK (singleton),
+    enabled BOOLEAN NOT NULL,
+    subject TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    updated_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (singleton)
+);
+-- Off until an admin has reviewed the wording.
+INSERT INTO welcome_email (enabled, subject, html_content, text_content)
+VALUES (
+    false,
+    'Welcome!',
+    '<p>Hi {{ name }},</p><p>Thank you for confirming your subscription. The next issue will be in your inbox soon.</p>',
+    E'Hi {{ name }},\n\nThank you for confirming your subscription. The next issue will be in your inbox soon.'
+);
+
+CREATE TABLE welcome_email_queue (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    enqueued_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscriber_id)
+);
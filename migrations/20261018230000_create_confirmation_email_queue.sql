@@ -0,0 +1,7 @@
+-- Confirmation emails sent by the background worker rather than during the request,
+-- e.g. for imports. The token is generated when the email goes out: only its hash is stored.
+CREATE TABLE confirmation_email_queue (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions(id),
+    enqueued_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscriber_id)
+);
@@ -0,0 +1,26 @@
+-- Who an issue goes to: confirmed members of any of `list_ids`, carrying at least one
+-- of `include_tag_ids` when there are any, and none of `exclude_tag_ids`.
+-- Both the recipient count on the publish form and the delivery queue read from here.
+CREATE FUNCTION segment_recipients(list_ids uuid[], include_tag_ids uuid[], exclude_tag_ids uuid[])
+RETURNS TABLE (subscriber_id uuid, email TEXT)
+LANGUAGE sql STABLE
+AS $$
+    SELECT DISTINCT s.id, s.email
+    FROM subscriptions s
+    JOIN list_memberships m ON m.subscriber_id = s.id
+    WHERE
+        s.status = 'confirmed' AND
+        m.status = 'confirmed' AND
+        m.list_id = ANY(list_ids) AND
+        (
+            cardinality(include_tag_ids) = 0 OR
+            EXISTS (
+                SELECT 1 FROM subscriber_tags t
+                WHERE t.subscriber_id = s.id AND t.tag_id = ANY(include_tag_ids)
+            )
+        ) AND
+        NOT EXISTS (
+            SELECT 1 FROM subscriber_tags t
+            WHERE t.subscriber_id = s.id AND t.tag_id = ANY(exclude_tag_ids)
+        )
+$$;
@@ -0,0 +1,21 @@
+-- Addresses are stored with their domain in lower case; rows from before that was
+-- the case are brought in line, unless that would collide with another subscriber.
+CREATE TEMPORARY TABLE lowercased_emails AS
+SELECT s.email AS old_email,
+       substring(s.email FROM '^(.*)@') || '@' || lower(substring(s.email FROM '@([^@]*)$')) AS new_email
+FROM subscriptions s
+WHERE s.email LIKE '%@%';
+DELETE FROM lowercased_emails WHERE old_email = new_email;
+DELETE FROM lowercased_emails l
+WHERE EXISTS (SELECT 1 FROM subscriptions s WHERE s.email = l.new_email)
+   OR (SELECT COUNT(*) FROM lowercased_emails o WHERE o.new_email = l.new_email) > 1;
+-- Queued deliveries refer to the address, not to the subscriber id.
+UPDATE issue_delivery_queue q
+SET subscriber_email = l.new_email
+FROM lowercased_emails l
+WHERE q.subscriber_email = l.old_email;
+UPDATE subscriptions s
+SET email = l.new_email
+FROM lowercased_emails l
+WHERE s.email = l.old_email;
+DROP TABLE lowercased_emails;
@@ -0,0 +1,4 @@
+-- The first backfill only covered ASCII domains, and the domains lowercased since
+-- kept their old case in this column. Only the application can convert an IDN
+-- to punycode: it fills the column in again when it starts.
+UPDATE subscriptions SET punycode_email = NULL;
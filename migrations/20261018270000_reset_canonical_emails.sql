@@ -0,0 +1,7 @@
+-- `lower(email)` is not the canonical address of a Gmail address, whose dots and
+-- `+tags` are dropped, nor of an IDN, whose domain is in punycode. The application
+-- fills those in again when it starts, with the rows left without one.
+UPDATE subscriptions
+SET canonical_email = NULL
+WHERE lower(split_part(email, '@', 2)) IN ('gmail.com', 'googlemail.com')
+   OR email ~ '[^\x01-\x7f]';
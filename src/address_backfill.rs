@@ -0,0 +1,74 @@
+use crate::domain::{EmailPolicy, SubscriberEmail};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Fills in the forms of an address that only the application can derive,
+/// for rows stored before it recorded them. Runs before the API and the worker
+/// start; a row it cannot fill in is logged, and looked at again on the next start.
+///
+/// Rows that turn out to be the same mailbox cannot all have its canonical address:
+/// the oldest one keeps it, and is the one signups, imports and data export
+/// requests for that mailbox match. The others are left without one and logged
+/// with the subscriber that holds it, for an admin to erase or keep.
+#[tracing::instrument(name = "Backfill the addresses of subscribers", skip_all)]
+pub async fn backfill_subscriber_addresses(pool: &PgPool, email_policy: &EmailPolicy) -> Result<(), anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, email, canonical_email IS NULL AS "needs_canonical_email!"
+        FROM subscriptions
+        WHERE punycode_email IS NULL OR canonical_email IS NULL
+        ORDER BY subscribed_at, id
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+    for row in rows {
+        let email = match SubscriberEmail::parse(row.email) {
+            Ok(email) => email,
+            Err(e) => {
+                tracing::warn!(
+                    subscriber_id = %row.id,
+                    error.message = %e,
+                    "Cannot backfill the address of a subscriber: the stored address is invalid."
+                );
+                continue;
+            }
+        };
+        sqlx::query!(
+            r#"UPDATE subscriptions SET punycode_email = $2 WHERE id = $1"#,
+            row.id,
+            email.punycode()
+        )
+        .execute(pool)
+        .await?;
+        if row.needs_canonical_email {
+            backfill_canonical_email(pool, row.id, &email_policy.canonical(&email)).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn backfill_canonical_email(pool: &PgPool, subscriber_id: Uuid, canonical_email: &str) -> Result<(), anyhow::Error> {
+    let holder = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE canonical_email = $1"#,
+        canonical_email
+    )
+    .fetch_optional(pool)
+    .await?;
+    if let Some(holder) = holder {
+        tracing::warn!(
+            subscriber_id = %subscriber_id,
+            duplicate_of = %holder.id,
+            "A subscriber is a duplicate of an older one with another spelling of the same mailbox."
+        );
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"UPDATE subscriptions SET canonical_email = $2 WHERE id = $1"#,
+        subscriber_id,
+        canonical_email
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
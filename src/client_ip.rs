@@ -0,0 +1,101 @@
+use actix_web::{web, HttpRequest};
+use std::net::IpAddr;
+
+/// Proxies in front of the application, whose `X-Forwarded-For` header is believed.
+/// Anyone else can put whatever they like in that header.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+impl TrustedProxies {
+    /// The peer address, unless the peer is a trusted proxy: then the forwarded
+    /// chain is walked from the right, past the trusted hops, to the first address
+    /// that none of our proxies vouches for.
+    pub fn client_ip(&self, request: &HttpRequest) -> Option<IpAddr> {
+        let mut client = request.peer_addr()?.ip();
+        let forwarded_for: Vec<&str> = request
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(','))
+            .collect();
+        for hop in forwarded_for.into_iter().rev() {
+            if !self.0.contains(&client) {
+                break;
+            }
+            match hop.trim().parse() {
+                Ok(ip) => client = ip,
+                // The trusted proxy passed on something it did not check: stop at the proxy.
+                Err(_) => break
+            }
+        }
+        Some(client)
+    }
+}
+
+/// Who sent `request`, as far as we can tell; see `TrustedProxies::client_ip`.
+pub fn client_ip(request: &HttpRequest) -> Option<IpAddr> {
+    match request.app_data::<web::Data<TrustedProxies>>() {
+        Some(trusted_proxies) => trusted_proxies.client_ip(request),
+        None => request.peer_addr().map(|addr| addr.ip())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrustedProxies;
+    use actix_web::test::TestRequest;
+    use claim::assert_some_eq;
+    use std::net::IpAddr;
+
+    const PROXY: &str = "10.0.0.1";
+    const CLIENT: &str = "203.0.113.7";
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn request_from(peer: &str, forwarded_for: Option<&str>) -> actix_web::HttpRequest {
+        let mut request = TestRequest::default().peer_addr(format!("{}:4242", peer).parse().unwrap());
+        if let Some(forwarded_for) = forwarded_for {
+            request = request.insert_header(("X-Forwarded-For", forwarded_for));
+        }
+        request.to_http_request()
+    }
+
+    #[test]
+    fn the_forwarded_header_of_an_untrusted_peer_is_ignored() {
+        let request = request_from(CLIENT, Some("198.51.100.1"));
+        assert_some_eq!(TrustedProxies::default().client_ip(&request), ip(CLIENT));
+    }
+
+    #[test]
+    fn a_trusted_proxy_names_the_client() {
+        let request = request_from(PROXY, Some(CLIENT));
+        assert_some_eq!(TrustedProxies(vec![ip(PROXY)]).client_ip(&request), ip(CLIENT));
+    }
+
+    #[test]
+    fn addresses_prepended_by_the_client_are_ignored() {
+        let request = request_from(PROXY, Some(&format!("198.51.100.1, {}", CLIENT)));
+        assert_some_eq!(TrustedProxies(vec![ip(PROXY)]).client_ip(&request), ip(CLIENT));
+    }
+
+    #[test]
+    fn chained_trusted_proxies_are_skipped() {
+        let request = request_from(PROXY, Some(&format!("{}, 10.0.0.2", CLIENT)));
+        let trusted_proxies = TrustedProxies(vec![ip(PROXY), ip("10.0.0.2")]);
+        assert_some_eq!(trusted_proxies.client_ip(&request), ip(CLIENT));
+    }
+
+    #[test]
+    fn a_trusted_proxy_without_a_forwarded_header_is_the_client() {
+        let request = request_from(PROXY, None);
+        assert_some_eq!(TrustedProxies(vec![ip(PROXY)]).client_ip(&request), ip(PROXY));
+    }
+
+    #[test]
+    fn a_garbled_forwarded_header_stops_at_the_proxy() {
+        let request = request_from(PROXY, Some("not an address"));
+        assert_some_eq!(TrustedProxies(vec![ip(PROXY)]).client_ip(&request), ip(PROXY));
+    }
+}
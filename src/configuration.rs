@@ -1,4 +1,5 @@
-use crate::{domain::SubscriberEmail, email_client::EmailClient};
+use crate::{domain::{ChallengeRules, EmailPolicy, SubscriberEmail}, email_client::EmailClient};
+use anyhow::Context;
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
@@ -9,7 +10,107 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
-    pub redis_uri: Secret<String>
+    pub redis_uri: Secret<String>,
+    pub rate_limit: RateLimitSettings,
+    pub bot_protection: BotProtectionSettings,
+    pub email_policy: EmailPolicySettings,
+    pub mx_check: MxCheckSettings,
+    #[serde(default)]
+    pub widget: WidgetSettings
+}
+
+/// Third-party sites allowed to embed the subscribe widget.
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct WidgetSettings {
+    #[serde(default)]
+    pub sites: Vec<WidgetSite>
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct WidgetSite {
+    /// Recorded as the source of the subscribers the site brings in.
+    pub id: String,
+    /// e.g. `https://blog.example.com`, as sent in the `Origin` header.
+    pub origin: String
+}
+
+/// Limits on the public endpoints that send email on behalf of anonymous callers.
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    /// Namespace for the counters in Redis, so that deployments can share an instance.
+    pub key_prefix: String,
+    pub per_ip: RateLimit,
+    pub per_email: RateLimit
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimit {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: usize
+}
+
+/// Checks that tell people filling in the subscribe form apart from scripts.
+#[derive(serde::Deserialize, Clone)]
+pub struct BotProtectionSettings {
+    /// Turn away submissions that do not return a challenge from `/subscriptions/challenge`.
+    pub require_challenge: bool,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_submit_seconds: i64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub challenge_ttl_seconds: i64,
+    /// Leading zero bits the browser has to find; 0 turns the proof-of-work off.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub proof_of_work_difficulty: u32
+}
+
+impl BotProtectionSettings {
+    pub fn challenge_rules(&self) -> ChallengeRules {
+        ChallengeRules {
+            min_age: chrono::Duration::seconds(self.min_submit_seconds),
+            max_age: chrono::Duration::seconds(self.challenge_ttl_seconds),
+            difficulty: self.proof_of_work_difficulty
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailPolicySettings {
+    /// Treat Gmail addresses that only differ in dots or `+tags` as one subscriber.
+    pub canonicalize_provider_aliases: bool,
+    /// One domain per line; blank lines and lines starting with `#` are ignored.
+    pub disposable_domains_file: Option<String>
+}
+
+impl EmailPolicySettings {
+    pub fn policy(&self) -> Result<EmailPolicy, anyhow::Error> {
+        let disposable_domains = match &self.disposable_domains_file {
+            Some(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the disposable domains from {}.", path))?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(String::from)
+                .collect(),
+            None => Vec::new()
+        };
+        Ok(EmailPolicy::new(self.canonicalize_provider_aliases, disposable_domains))
+    }
+}
+
+/// Looking up whether the domain of a new address accepts email.
+#[derive(serde::Deserialize, Clone)]
+pub struct MxCheckSettings {
+    pub enabled: bool,
+    /// `<ip>:<port>` of the DNS resolver to ask.
+    pub resolver: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    /// Let the subscription through when the resolver cannot be reached or does not answer.
+    pub fail_open: bool,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cache_ttl_seconds: u64
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -57,7 +158,18 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
-    pub hmac_secret: Secret<String>
+    pub hmac_secret: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub subscription_token_ttl_hours: i64,
+    /// Load balancers or reverse proxies whose `X-Forwarded-For` names the client.
+    #[serde(default)]
+    pub trusted_proxies: Vec<std::net::IpAddr>
+}
+
+impl ApplicationSettings {
+    pub fn subscription_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.subscription_token_ttl_hours)
+    }
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
@@ -94,7 +206,7 @@ impl DatabaseSettings {
         PgConnectOptions::new()
             .host(&self.host)
             .username(&self.username)
-            .password(&self.password.expose_secret())
+            .password(self.password.expose_secret())
             .port(self.port)
             .ssl_mode(ssl_mode)
     }
@@ -0,0 +1,117 @@
+use crate::domain::{NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::{delete_task, dequeue_task, ExecutionOutcome, QueuedTask};
+use crate::routes::{generate_subscription_token, send_confirmation_email, store_token};
+use crate::startup::ApplicationBaseUrl;
+use anyhow::Context;
+use sqlx::postgres::PgArguments;
+use sqlx::query::Query;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+/// Leaves the confirmation email of a pending subscriber to the background worker,
+/// for callers that add too many subscribers at once to email them inline.
+#[tracing::instrument(name = "Queue a confirmation email", skip(transaction))]
+pub(crate) async fn enqueue_confirmation_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_queue (subscriber_id)
+        VALUES ($1)
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// The token is created here, as only its hash can be stored while the email waits.
+#[tracing::instrument(
+    skip_all,
+    fields(subscriber_id = tracing::field::Empty),
+    err
+)]
+pub async fn try_send_confirmation_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let (mut transaction, task) = match dequeue_task::<ConfirmationEmailTask>(pool).await? {
+        Some(task) => task,
+        None => return Ok(ExecutionOutcome::EmptyQueue)
+    };
+    let subscriber_id = task.subscriber_id;
+    Span::current().record("subscriber_id", &display(subscriber_id));
+
+    match get_pending_subscriber(pool, subscriber_id).await? {
+        None => {
+            tracing::info!("Skipping a subscriber who is no longer awaiting confirmation.");
+        }
+        Some(Err(e)) => {
+            tracing::error!(
+                error.message = %e,
+                "Skipping a pending subscriber. Their stored contact details are invalid"
+            );
+        }
+        Some(Ok(subscriber)) => {
+            let subscription_token = generate_subscription_token();
+            store_token(&mut transaction, subscriber_id, &subscription_token)
+                .await
+                .context("Failed to store a confirmation token.")?;
+            if let Err(e) = send_confirmation_email(email_client, subscriber, &base_url.0, &subscription_token).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a queued confirmation email. Skipping."
+                );
+            }
+        }
+    }
+
+    delete_task(transaction, &task).await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[derive(sqlx::FromRow)]
+struct ConfirmationEmailTask {
+    subscriber_id: Uuid
+}
+impl QueuedTask for ConfirmationEmailTask {
+    const QUEUE: &'static str = "confirmation_email_queue";
+    const KEY: &'static [&'static str] = &["subscriber_id"];
+
+    fn bind_key<'q>(&'q self, query: Query<'q, Postgres, PgArguments>) -> Query<'q, Postgres, PgArguments> {
+        query.bind(self.subscriber_id)
+    }
+}
+
+/// `None` once the subscriber has confirmed or left.
+#[tracing::instrument(skip_all)]
+async fn get_pending_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid
+) -> Result<Option<Result<NewSubscriber, String>>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT email, punycode_email, name, attributes
+        FROM subscriptions
+        WHERE id = $1 AND status = $2
+        "#,
+        subscriber_id,
+        SubscriptionStatus::PendingConfirmation as SubscriptionStatus
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(r.map(|r| {
+        Ok(NewSubscriber {
+            email: SubscriberEmail::from_stored(r.email, r.punycode_email)?,
+            name: SubscriberName::parse(r.name)?,
+            attributes: SubscriberAttributes::from_json(r.attributes)
+        })
+    }))
+}
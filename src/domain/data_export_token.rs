@@ -0,0 +1,129 @@
+use anyhow::Context;
+use chrono::{DateTime, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Signed, short-lived token that lets a subscriber download their own data.
+/// Like `UnsubscribeToken` it is never stored; the expiry is part of what is signed.
+#[derive(Debug)]
+pub struct DataExportToken(String);
+
+impl DataExportToken {
+    pub fn generate(subscriber_id: Uuid, expires_at: i64, secret: &Secret<String>) -> Self {
+        let tag = mac(subscriber_id, expires_at, secret).finalize().into_bytes();
+        Self(hex::encode(tag))
+    }
+
+    /// `expires_at` is a Unix timestamp, in seconds.
+    pub fn verify(
+        token: &str,
+        subscriber_id: Uuid,
+        expires_at: i64,
+        now: DateTime<Utc>,
+        secret: &Secret<String>
+    ) -> Result<(), DataExportTokenError> {
+        let tag = hex::decode(token)
+            .context("The data export token is not valid hex.")
+            .map_err(DataExportTokenError::Invalid)?;
+        mac(subscriber_id, expires_at, secret)
+            .verify_slice(&tag)
+            .context("The data export token does not match the subscriber.")
+            .map_err(DataExportTokenError::Invalid)?;
+        if Utc.timestamp(expires_at, 0) <= now {
+            return Err(DataExportTokenError::Expired);
+        }
+        Ok(())
+    }
+}
+
+impl AsRef<str> for DataExportToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DataExportTokenError {
+    #[error("The data export link is invalid.")]
+    Invalid(#[source] anyhow::Error),
+    #[error("The data export link has expired.")]
+    Expired
+}
+
+fn mac(subscriber_id: Uuid, expires_at: i64, secret: &Secret<String>) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size.");
+    mac.update(b"data-export:");
+    mac.update(subscriber_id.as_bytes());
+    mac.update(&expires_at.to_be_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DataExportToken, DataExportTokenError};
+    use chrono::{Duration, Utc};
+    use claim::{assert_matches, assert_ok};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    fn tomorrow() -> i64 {
+        (Utc::now() + Duration::days(1)).timestamp()
+    }
+
+    #[test]
+    fn a_generated_token_is_valid_until_it_expires() {
+        let subscriber_id = Uuid::new_v4();
+        let expires_at = tomorrow();
+        let token = DataExportToken::generate(subscriber_id, expires_at, &secret());
+        assert_ok!(DataExportToken::verify(token.as_ref(), subscriber_id, expires_at, Utc::now(), &secret()));
+    }
+
+    #[test]
+    fn an_expired_token_is_rejected() {
+        let subscriber_id = Uuid::new_v4();
+        let expires_at = tomorrow();
+        let token = DataExportToken::generate(subscriber_id, expires_at, &secret());
+        let the_day_after = Utc::now() + Duration::days(2);
+        assert_matches!(
+            DataExportToken::verify(token.as_ref(), subscriber_id, expires_at, the_day_after, &secret()),
+            Err(DataExportTokenError::Expired)
+        );
+    }
+
+    #[test]
+    fn a_token_cannot_be_extended_by_changing_its_expiry() {
+        let subscriber_id = Uuid::new_v4();
+        let token = DataExportToken::generate(subscriber_id, tomorrow(), &secret());
+        assert_matches!(
+            DataExportToken::verify(token.as_ref(), subscriber_id, tomorrow() + 3600, Utc::now(), &secret()),
+            Err(DataExportTokenError::Invalid(_))
+        );
+    }
+
+    #[test]
+    fn a_token_is_rejected_for_a_different_subscriber() {
+        let expires_at = tomorrow();
+        let token = DataExportToken::generate(Uuid::new_v4(), expires_at, &secret());
+        assert_matches!(
+            DataExportToken::verify(token.as_ref(), Uuid::new_v4(), expires_at, Utc::now(), &secret()),
+            Err(DataExportTokenError::Invalid(_))
+        );
+    }
+
+    #[test]
+    fn an_unsubscribe_token_is_not_a_data_export_token() {
+        let subscriber_id = Uuid::new_v4();
+        let unsubscribe_token = crate::domain::UnsubscribeToken::generate(subscriber_id, &secret());
+        assert_matches!(
+            DataExportToken::verify(unsubscribe_token.as_ref(), subscriber_id, tomorrow(), Utc::now(), &secret()),
+            Err(DataExportTokenError::Invalid(_))
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use crate::domain::SubscriberEmail;
+use std::collections::HashSet;
+
+/// Domains whose mailboxes ignore dots in the local part.
+const GMAIL_DOMAINS: [&str; 2] = ["gmail.com", "googlemail.com"];
+
+/// Which addresses may subscribe, and when two addresses are the same mailbox.
+#[derive(Debug, Default)]
+pub struct EmailPolicy {
+    canonicalize_provider_aliases: bool,
+    disposable_domains: HashSet<String>
+}
+
+impl EmailPolicy {
+    pub fn new(canonicalize_provider_aliases: bool, disposable_domains: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            canonicalize_provider_aliases,
+            disposable_domains: disposable_domains
+                .into_iter()
+                .map(|domain| domain.trim().trim_start_matches('.').to_lowercase())
+                .filter(|domain| !domain.is_empty())
+                .map(|domain| idna::domain_to_ascii(&domain).unwrap_or(domain))
+                .collect()
+        }
+    }
+
+    /// The key duplicates are detected on: the whole address in lower case, with the
+    /// domain in punycode so that either spelling of an IDN matches, and with
+    /// provider aliases turned on, Gmail's dots and `+tags` dropped, as Gmail delivers
+    /// `u.rsula+news@googlemail.com` to the same mailbox as `ursula@gmail.com`.
+    pub fn canonical(&self, email: &SubscriberEmail) -> String {
+        let local_part = email.local_part().to_lowercase();
+        let domain = email.punycode_domain();
+        if self.canonicalize_provider_aliases && GMAIL_DOMAINS.contains(&domain) {
+            let local_part = local_part.split('+').next().unwrap_or_default().replace('.', "");
+            return format!("{}@{}", local_part, GMAIL_DOMAINS[0]);
+        }
+        format!("{}@{}", local_part, domain)
+    }
+
+    /// Turns away addresses at a listed domain or at any of its subdomains.
+    pub fn check(&self, email: &SubscriberEmail) -> Result<(), String> {
+        let domain = email.punycode_domain();
+        let is_disposable = std::iter::successors(Some(domain), |d| d.split_once('.').map(|(_, parent)| parent))
+            .any(|d| self.disposable_domains.contains(d));
+        if is_disposable {
+            return Err(format!(
+                "{} is a disposable email provider. Please subscribe with an address you intend to keep.",
+                email.domain()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailPolicy;
+    use crate::domain::SubscriberEmail;
+    use claim::{assert_err, assert_ok};
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    #[test]
+    fn addresses_differing_only_in_case_are_the_same() {
+        let policy = EmailPolicy::default();
+        assert_eq!(policy.canonical(&email("Ursula@Example.com")), policy.canonical(&email("ursula@example.com")));
+    }
+
+    #[test]
+    fn gmail_aliases_are_kept_apart_by_default() {
+        let policy = EmailPolicy::default();
+        assert_eq!(policy.canonical(&email("ursula.le.guin+news@gmail.com")), "ursula.le.guin+news@gmail.com");
+    }
+
+    #[test]
+    fn gmail_aliases_are_the_same_mailbox_when_turned_on() {
+        let policy = EmailPolicy::new(true, vec![]);
+        assert_eq!(policy.canonical(&email("Ursula.Le.Guin+news@googlemail.com")), "ursulaleguin@gmail.com");
+        assert_eq!(policy.canonical(&email("ursulaleguin@gmail.com")), "ursulaleguin@gmail.com");
+    }
+
+    #[test]
+    fn other_providers_keep_their_dots_and_tags() {
+        let policy = EmailPolicy::new(true, vec![]);
+        assert_eq!(policy.canonical(&email("ursula.le.guin+news@example.com")), "ursula.le.guin+news@example.com");
+    }
+
+    #[test]
+    fn both_spellings_of_an_idn_are_the_same_address() {
+        let policy = EmailPolicy::default();
+        assert_eq!(policy.canonical(&email("josé@bücher.de")), "josé@xn--bcher-kva.de");
+        assert_eq!(policy.canonical(&email("José@xn--bcher-kva.de")), "josé@xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn disposable_domains_and_their_subdomains_are_rejected() {
+        let policy = EmailPolicy::new(false, vec!["Mailinator.com".to_string()]);
+        assert_err!(policy.check(&email("ursula@mailinator.com")));
+        assert_err!(policy.check(&email("ursula@eu.mailinator.com")));
+        assert_ok!(policy.check(&email("ursula@notmailinator.com")));
+        assert_ok!(policy.check(&email("ursula@gmail.com")));
+    }
+
+    #[test]
+    fn a_listed_idn_is_rejected_in_either_spelling() {
+        let policy = EmailPolicy::new(false, vec!["müll.example".to_string()]);
+        assert_err!(policy.check(&email("josé@müll.example")));
+        assert_err!(policy.check(&email("josé@xn--mll-hoa.example")));
+    }
+}
@@ -0,0 +1,74 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+/// What is left of an erased subscriber: enough to recognise their address
+/// if it shows up again, not enough to recover it.
+/// It is keyed with the application secret, so that a list of candidate
+/// addresses cannot be checked against the table offline.
+#[derive(Debug, PartialEq)]
+pub struct ErasureTombstone(String);
+
+impl ErasureTombstone {
+    pub fn compute(email: &str, secret: &Secret<String>) -> Self {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size.");
+        mac.update(b"erasure:");
+        mac.update(email.trim().to_lowercase().as_bytes());
+        Self(hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+impl AsRef<str> for ErasureTombstone {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ErasureTombstone;
+    use secrecy::Secret;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    #[test]
+    fn the_same_address_always_gives_the_same_tombstone() {
+        assert_eq!(
+            ErasureTombstone::compute("ursula@domain.com", &secret()),
+            ErasureTombstone::compute("ursula@domain.com", &secret())
+        );
+    }
+
+    #[test]
+    fn the_tombstone_ignores_case_and_surrounding_whitespace() {
+        assert_eq!(
+            ErasureTombstone::compute("ursula@domain.com", &secret()),
+            ErasureTombstone::compute(" Ursula@Domain.com ", &secret())
+        );
+    }
+
+    #[test]
+    fn different_addresses_give_different_tombstones() {
+        assert_ne!(
+            ErasureTombstone::compute("ursula@domain.com", &secret()),
+            ErasureTombstone::compute("octavia@domain.com", &secret())
+        );
+    }
+
+    #[test]
+    fn the_tombstone_does_not_contain_the_address() {
+        let tombstone = ErasureTombstone::compute("ursula@domain.com", &secret());
+        assert!(!tombstone.as_ref().contains("ursula"));
+    }
+
+    #[test]
+    fn the_tombstone_depends_on_the_secret() {
+        assert_ne!(
+            ErasureTombstone::compute("ursula@domain.com", &secret()),
+            ErasureTombstone::compute("ursula@domain.com", &Secret::new("another-key".into()))
+        );
+    }
+}
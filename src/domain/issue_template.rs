@@ -0,0 +1,213 @@
+use crate::domain::SubscriberAttributes;
+
+/// Issue content with per-recipient placeholders.
+///
+/// A placeholder is `{{ name }}`, `{{ email }}` or `{{ attributes.<key> }}`,
+/// optionally followed by a fallback: `{{ attributes.company | default("your team") }}`.
+/// A variable without a value renders as its fallback, or as nothing when there is none.
+#[derive(Debug)]
+pub struct IssueTemplate(Vec<Part>);
+
+#[derive(Debug)]
+enum Part {
+    Text(String),
+    Placeholder {
+        variable: Variable,
+        fallback: Option<String>
+    }
+}
+
+#[derive(Debug)]
+enum Variable {
+    Name,
+    Email,
+    Attribute(String)
+}
+
+/// What a template can refer to when rendered for one recipient.
+pub struct TemplateContext<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub attributes: &'a SubscriberAttributes
+}
+
+impl IssueTemplate {
+    pub fn parse(s: &str) -> Result<IssueTemplate, String> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let after_open = &rest[start + 2..];
+            let end = after_open.find("}}").ok_or_else(|| {
+                format!("The placeholder starting with `{}` is never closed.", snippet(&rest[start..]))
+            })?;
+            parts.push(parse_placeholder(&after_open[..end])?);
+            rest = &after_open[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Self(parts))
+    }
+
+    pub fn render_text(&self, context: &TemplateContext) -> String {
+        self.render(context, |value| value.to_string())
+    }
+
+    /// Values are escaped: subscribers control their own name and attributes.
+    pub fn render_html(&self, context: &TemplateContext) -> String {
+        self.render(context, htmlescape::encode_minimal)
+    }
+
+    fn render(&self, context: &TemplateContext, encode: impl Fn(&str) -> String) -> String {
+        let mut output = String::new();
+        for part in &self.0 {
+            match part {
+                Part::Text(text) => output.push_str(text),
+                Part::Placeholder { variable, fallback } => {
+                    let value = match variable {
+                        Variable::Name => Some(context.name.to_string()),
+                        Variable::Email => Some(context.email.to_string()),
+                        Variable::Attribute(key) => context.attributes.get(key)
+                    };
+                    if let Some(value) = value.as_deref().or(fallback.as_deref()) {
+                        output.push_str(&encode(value));
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+fn parse_placeholder(expression: &str) -> Result<Part, String> {
+    let (path, filter) = match expression.split_once('|') {
+        Some((path, filter)) => (path.trim(), Some(filter.trim())),
+        None => (expression.trim(), None)
+    };
+    let variable = match path.split_once('.') {
+        None if path == "name" => Variable::Name,
+        None if path == "email" => Variable::Email,
+        Some(("attributes", key)) if !key.is_empty() => Variable::Attribute(key.to_string()),
+        _ => {
+            return Err(format!(
+                "`{{{{ {} }}}}` refers to an unknown variable. \
+                Use `name`, `email` or `attributes.<key>`.",
+                path
+            ))
+        }
+    };
+    let fallback = match filter {
+        None => None,
+        Some(filter) => Some(parse_default_filter(filter).ok_or_else(|| {
+            format!(
+                "`{}` is not a valid fallback. Use `default(\"some text\")`.",
+                filter
+            )
+        })?)
+    };
+    Ok(Part::Placeholder { variable, fallback })
+}
+
+fn parse_default_filter(filter: &str) -> Option<String> {
+    let argument = filter
+        .strip_prefix("default")?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .trim();
+    let quote = argument.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let literal = argument.strip_prefix(quote)?.strip_suffix(quote)?;
+    if literal.contains(quote) {
+        return None;
+    }
+    Some(literal.to_string())
+}
+
+fn snippet(s: &str) -> String {
+    s.chars().take(20).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{IssueTemplate, SubscriberAttributes, TemplateContext};
+    use claim::assert_err;
+
+    fn render_text(template: &str, attributes: serde_json::Value) -> String {
+        let attributes = SubscriberAttributes::from_json(attributes);
+        let context = TemplateContext {
+            name: "Ursula",
+            email: "ursula@example.com",
+            attributes: &attributes
+        };
+        IssueTemplate::parse(template).unwrap().render_text(&context)
+    }
+
+    #[test]
+    fn text_without_placeholders_is_left_untouched() {
+        assert_eq!(render_text("Hello, world! {not a placeholder}", serde_json::json!({})), "Hello, world! {not a placeholder}");
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_the_recipient_details() {
+        let rendered = render_text(
+            "Hi {{ name }} ({{email}}) from {{ attributes.company }}!",
+            serde_json::json!({ "company": "ACME" })
+        );
+        assert_eq!(rendered, "Hi Ursula (ursula@example.com) from ACME!");
+    }
+
+    #[test]
+    fn undefined_attributes_render_as_nothing() {
+        let rendered = render_text("Hi from {{ attributes.company }}!", serde_json::json!({}));
+        assert_eq!(rendered, "Hi from !");
+    }
+
+    #[test]
+    fn undefined_attributes_use_the_fallback_when_there_is_one() {
+        let template = r#"Hi from {{ attributes.company | default("your team") }}!"#;
+        assert_eq!(render_text(template, serde_json::json!({})), "Hi from your team!");
+        assert_eq!(render_text(template, serde_json::json!({ "company": "ACME" })), "Hi from ACME!");
+    }
+
+    #[test]
+    fn html_rendering_escapes_values() {
+        let attributes = SubscriberAttributes::from_json(serde_json::json!({ "company": "<b>ACME</b>" }));
+        let context = TemplateContext {
+            name: "Ursula",
+            email: "ursula@example.com",
+            attributes: &attributes
+        };
+        let rendered = IssueTemplate::parse("<p>{{ attributes.company }}</p>")
+            .unwrap()
+            .render_html(&context);
+        assert_eq!(rendered, "<p>&lt;b&gt;ACME&lt;/b&gt;</p>");
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        assert_err!(IssueTemplate::parse("Hi {{ name"));
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        for template in &["{{ }}", "{{ nme }}", "{{ attributes }}", "{{ attributes. }}", "{{ company.name }}"] {
+            assert_err!(IssueTemplate::parse(template));
+        }
+    }
+
+    #[test]
+    fn malformed_fallbacks_are_rejected() {
+        for template in &[
+            "{{ name | }}",
+            "{{ name | upper }}",
+            "{{ name | default(friend) }}",
+            r#"{{ name | default("friend) }}"#,
+            r#"{{ name | default("a"b") }}"#
+        ] {
+            assert_err!(IssueTemplate::parse(template));
+        }
+    }
+}
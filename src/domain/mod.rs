@@ -1,7 +1,23 @@
+mod data_export_token;
+mod email_policy;
+mod erasure_tombstone;
+mod issue_template;
 mod new_subscriber;
+mod subscribe_challenge;
+mod subscriber_attributes;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_status;
+mod unsubscribe_token;
 
+pub use data_export_token::{DataExportToken, DataExportTokenError};
+pub use email_policy::EmailPolicy;
+pub use erasure_tombstone::ErasureTombstone;
+pub use issue_template::{IssueTemplate, TemplateContext};
 pub use new_subscriber::NewSubscriber;
+pub use subscribe_challenge::{ChallengeRules, SubscribeChallenge, SubscribeChallengeError};
+pub use subscriber_attributes::SubscriberAttributes;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscription_status::{InvalidStatusTransition, SubscriptionStatus};
+pub use unsubscribe_token::UnsubscribeToken;
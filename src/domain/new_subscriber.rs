@@ -1,6 +1,7 @@
-use crate::domain::{SubscriberEmail, SubscriberName};
+use crate::domain::{SubscriberAttributes, SubscriberEmail, SubscriberName};
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub attributes: SubscriberAttributes,
 }
@@ -0,0 +1,231 @@
+use anyhow::Context;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use rand::{Rng, thread_rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+/// Handed out with the subscribe form and sent back with it: `<issued at>.<salt>.<tag>`.
+/// It is never stored. The signature proves when the form was served, which bounds
+/// how fast it may be submitted, and it is the input of the optional proof-of-work.
+/// The work also covers the submitted address, so one solution buys one signup only.
+#[derive(Debug)]
+pub struct SubscribeChallenge(String);
+
+/// What a challenge must satisfy to be accepted.
+pub struct ChallengeRules {
+    pub min_age: Duration,
+    pub max_age: Duration,
+    /// Leading zero bits required of `SHA-256(<challenge>:<email>:<nonce>)`; 0 asks for no work.
+    pub difficulty: u32
+}
+
+impl SubscribeChallenge {
+    pub fn issue(now: DateTime<Utc>, secret: &Secret<String>) -> Self {
+        let issued_at = now.timestamp();
+        let salt = hex::encode(thread_rng().gen::<[u8; 8]>());
+        let tag = mac(issued_at, &salt, secret).finalize().into_bytes();
+        Self(format!("{}.{}.{}", issued_at, salt, hex::encode(tag)))
+    }
+
+    pub fn verify(
+        challenge: &str,
+        email: &str,
+        nonce: &str,
+        rules: &ChallengeRules,
+        now: DateTime<Utc>,
+        secret: &Secret<String>
+    ) -> Result<(), SubscribeChallengeError> {
+        let mut parts = challenge.splitn(3, '.');
+        let (issued_at, salt, tag) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(issued_at), Some(salt), Some(tag)) => (issued_at, salt, tag),
+            _ => return Err(SubscribeChallengeError::Invalid(anyhow::anyhow!("The challenge is malformed.")))
+        };
+        let issued_at: i64 = issued_at
+            .parse()
+            .context("The challenge timestamp is not a number.")
+            .map_err(SubscribeChallengeError::Invalid)?;
+        let tag = hex::decode(tag)
+            .context("The challenge tag is not valid hex.")
+            .map_err(SubscribeChallengeError::Invalid)?;
+        mac(issued_at, salt, secret)
+            .verify_slice(&tag)
+            .context("The challenge was not issued by us.")
+            .map_err(SubscribeChallengeError::Invalid)?;
+
+        let age = now - Utc.timestamp(issued_at, 0);
+        if age < rules.min_age {
+            return Err(SubscribeChallengeError::TooFast);
+        }
+        if age > rules.max_age {
+            return Err(SubscribeChallengeError::Expired);
+        }
+        if leading_zero_bits(&work(challenge, email, nonce)) < rules.difficulty {
+            return Err(SubscribeChallengeError::InsufficientWork);
+        }
+        Ok(())
+    }
+}
+
+impl AsRef<str> for SubscribeChallenge {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SubscribeChallengeError {
+    #[error("The challenge is invalid.")]
+    Invalid(#[source] anyhow::Error),
+    #[error("The form was submitted too quickly.")]
+    TooFast,
+    #[error("The form has expired.")]
+    Expired,
+    #[error("The proof of work is missing or insufficient.")]
+    InsufficientWork
+}
+
+fn mac(issued_at: i64, salt: &str, secret: &Secret<String>) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size.");
+    mac.update(b"subscribe-challenge:");
+    mac.update(&issued_at.to_be_bytes());
+    mac.update(salt.as_bytes());
+    mac
+}
+
+fn work(challenge: &str, email: &str, nonce: &str) -> [u8; 32] {
+    Sha256::digest(format!("{}:{}:{}", challenge, email, nonce).as_bytes()).into()
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChallengeRules, SubscribeChallenge, SubscribeChallengeError};
+    use chrono::{Duration, Utc};
+    use claim::{assert_matches, assert_ok};
+    use secrecy::Secret;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    fn rules(difficulty: u32) -> ChallengeRules {
+        ChallengeRules {
+            min_age: Duration::seconds(3),
+            max_age: Duration::days(1),
+            difficulty
+        }
+    }
+
+    fn a_minute_ago() -> chrono::DateTime<Utc> {
+        Utc::now() - Duration::minutes(1)
+    }
+
+    const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+    fn solve(challenge: &str, difficulty: u32) -> String {
+        (0u64..)
+            .map(|nonce| nonce.to_string())
+            .find(|nonce| super::leading_zero_bits(&super::work(challenge, EMAIL, nonce)) >= difficulty)
+            .unwrap()
+    }
+
+    #[test]
+    fn a_challenge_without_work_is_accepted_when_none_is_asked_for() {
+        let challenge = SubscribeChallenge::issue(a_minute_ago(), &secret());
+        assert_ok!(SubscribeChallenge::verify(challenge.as_ref(), EMAIL, "", &rules(0), Utc::now(), &secret()));
+    }
+
+    #[test]
+    fn a_solved_challenge_is_accepted() {
+        let challenge = SubscribeChallenge::issue(a_minute_ago(), &secret());
+        let nonce = solve(challenge.as_ref(), 8);
+        assert_ok!(SubscribeChallenge::verify(challenge.as_ref(), EMAIL, &nonce, &rules(8), Utc::now(), &secret()));
+    }
+
+    #[test]
+    fn a_solution_only_holds_for_the_address_it_was_found_for() {
+        let challenge = SubscribeChallenge::issue(a_minute_ago(), &secret());
+        let solves = |email: &str, nonce: &str| {
+            super::leading_zero_bits(&super::work(challenge.as_ref(), email, nonce)) >= 8
+        };
+        let nonce = (0u64..)
+            .map(|nonce| nonce.to_string())
+            .find(|nonce| solves(EMAIL, nonce) && !solves("another@gmail.com", nonce))
+            .unwrap();
+        assert_ok!(SubscribeChallenge::verify(challenge.as_ref(), EMAIL, &nonce, &rules(8), Utc::now(), &secret()));
+        assert_matches!(
+            SubscribeChallenge::verify(challenge.as_ref(), "another@gmail.com", &nonce, &rules(8), Utc::now(), &secret()),
+            Err(SubscribeChallengeError::InsufficientWork)
+        );
+    }
+
+    #[test]
+    fn an_unsolved_challenge_is_rejected() {
+        let challenge = SubscribeChallenge::issue(a_minute_ago(), &secret());
+        // A nonce that solves difficulty 0 only.
+        let nonce = (0u64..)
+            .map(|nonce| nonce.to_string())
+            .find(|nonce| super::leading_zero_bits(&super::work(challenge.as_ref(), EMAIL, nonce)) == 0)
+            .unwrap();
+        assert_matches!(
+            SubscribeChallenge::verify(challenge.as_ref(), EMAIL, &nonce, &rules(8), Utc::now(), &secret()),
+            Err(SubscribeChallengeError::InsufficientWork)
+        );
+    }
+
+    #[test]
+    fn a_challenge_submitted_too_quickly_is_rejected() {
+        let challenge = SubscribeChallenge::issue(Utc::now(), &secret());
+        assert_matches!(
+            SubscribeChallenge::verify(challenge.as_ref(), EMAIL, "", &rules(0), Utc::now(), &secret()),
+            Err(SubscribeChallengeError::TooFast)
+        );
+    }
+
+    #[test]
+    fn an_old_challenge_is_rejected() {
+        let challenge = SubscribeChallenge::issue(Utc::now() - Duration::days(2), &secret());
+        assert_matches!(
+            SubscribeChallenge::verify(challenge.as_ref(), EMAIL, "", &rules(0), Utc::now(), &secret()),
+            Err(SubscribeChallengeError::Expired)
+        );
+    }
+
+    #[test]
+    fn a_challenge_cannot_be_backdated() {
+        let challenge = SubscribeChallenge::issue(Utc::now(), &secret());
+        let (_, rest) = challenge.as_ref().split_once('.').unwrap();
+        let backdated = format!("{}.{}", a_minute_ago().timestamp(), rest);
+        assert_matches!(
+            SubscribeChallenge::verify(&backdated, EMAIL, "", &rules(0), Utc::now(), &secret()),
+            Err(SubscribeChallengeError::Invalid(_))
+        );
+    }
+
+    #[test]
+    fn a_challenge_signed_with_another_secret_is_rejected() {
+        let challenge = SubscribeChallenge::issue(a_minute_ago(), &Secret::new("another-key".to_string()));
+        assert_matches!(
+            SubscribeChallenge::verify(challenge.as_ref(), EMAIL, "", &rules(0), Utc::now(), &secret()),
+            Err(SubscribeChallengeError::Invalid(_))
+        );
+    }
+
+    #[test]
+    fn leading_zero_bits_span_bytes() {
+        assert_eq!(super::leading_zero_bits(&[0, 0b0001_0000, 0]), 11);
+        assert_eq!(super::leading_zero_bits(&[0b1000_0000]), 0);
+    }
+}
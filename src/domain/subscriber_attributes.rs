@@ -0,0 +1,94 @@
+use serde_json::{Map, Value};
+use unicode_segmentation::UnicodeSegmentation;
+
+const MAX_ATTRIBUTES: usize = 20;
+
+/// Free-form details about a subscriber (e.g. `company`), available
+/// to issue templates as `{{ attributes.<key> }}`.
+#[derive(Debug, Default)]
+pub struct SubscriberAttributes(Map<String, Value>);
+
+impl SubscriberAttributes {
+    pub fn parse(pairs: Vec<(String, String)>) -> Result<SubscriberAttributes, String> {
+        if pairs.len() > MAX_ATTRIBUTES {
+            return Err(format!("A subscriber cannot have more than {} attributes.", MAX_ATTRIBUTES));
+        }
+        let mut attributes = Map::new();
+        for (key, value) in pairs {
+            let is_valid_key = !key.is_empty()
+                && key.len() <= 64
+                && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+            if !is_valid_key {
+                return Err(format!("{} is not a valid attribute name.", key));
+            }
+            if value.graphemes(true).count() > 256 {
+                return Err(format!("The value of the {} attribute is too long.", key));
+            }
+            attributes.insert(key, Value::String(value));
+        }
+        Ok(Self(attributes))
+    }
+
+    /// Wraps attributes loaded back from the database, which were validated on the way in.
+    pub fn from_json(value: Value) -> SubscriberAttributes {
+        match value {
+            Value::Object(attributes) => Self(attributes),
+            _ => Self::default()
+        }
+    }
+
+    /// The attribute as text, or `None` if it is missing or null.
+    pub fn get(&self, key: &str) -> Option<String> {
+        match self.0.get(key)? {
+            Value::Null => None,
+            Value::String(s) => Some(s.clone()),
+            other => Some(other.to_string())
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberAttributes;
+    use claim::{assert_err, assert_none, assert_ok};
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn snake_case_keys_are_accepted() {
+        let attributes = SubscriberAttributes::parse(vec![pair("company_name", "ACME")]).unwrap();
+        assert_eq!(attributes.get("company_name").as_deref(), Some("ACME"));
+    }
+
+    #[test]
+    fn keys_with_other_characters_are_rejected() {
+        for key in &["", "Company", "company.name", "company name", "{{"] {
+            assert_err!(SubscriberAttributes::parse(vec![pair(key, "ACME")]));
+        }
+    }
+
+    #[test]
+    fn values_longer_than_256_graphemes_are_rejected() {
+        assert_ok!(SubscriberAttributes::parse(vec![pair("company", &"ё".repeat(256))]));
+        assert_err!(SubscriberAttributes::parse(vec![pair("company", &"a".repeat(257))]));
+    }
+
+    #[test]
+    fn too_many_attributes_are_rejected() {
+        let pairs = (0..21).map(|i| pair(&format!("key_{}", i), "value")).collect();
+        assert_err!(SubscriberAttributes::parse(pairs));
+    }
+
+    #[test]
+    fn null_and_missing_attributes_are_undefined() {
+        let attributes = SubscriberAttributes::from_json(serde_json::json!({ "company": null }));
+        assert_none!(attributes.get("company"));
+        assert_none!(attributes.get("role"));
+    }
+}
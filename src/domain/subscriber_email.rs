@@ -1,26 +1,79 @@
 use std::fmt::Formatter;
+use unicode_normalization::UnicodeNormalization;
 use validator::validate_email;
 
+/// An address in two forms: for display, with its domain in Unicode (`josé@bücher.de`),
+/// and for delivery, with its domain in punycode (`josé@xn--bcher-kva.de`).
+/// Internationalized local parts have no ASCII form; they need an SMTPUTF8 server either way.
 #[derive(Debug)]
-pub struct SubscriberEmail(String);
+pub struct SubscriberEmail {
+    email: String,
+    punycode_email: String
+}
 
 impl SubscriberEmail {
+    /// Domains are case-insensitive, so the domain part is stored in lower case.
+    /// The local part is kept as typed, bar Unicode normalisation (NFC):
+    /// only the receiving server knows what it means.
     pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not a valid subscriber email.", s))
+        let invalid = || format!("{} is not a valid subscriber email.", s);
+        let (local_part, domain) = s.rsplit_once('@').ok_or_else(invalid)?;
+        let local_part: String = local_part.nfc().collect();
+        let punycode_domain = idna::domain_to_ascii(domain).map_err(|_| invalid())?;
+        if local_part.chars().any(|c| !c.is_ascii() && (c.is_whitespace() || c.is_control())) {
+            return Err(invalid());
+        }
+        // `validate_email` only knows ASCII local parts; SMTPUTF8 allows any other printable character.
+        let ascii_local_part: String = local_part
+            .chars()
+            .map(|c| if c.is_ascii() { c } else { 'a' })
+            .collect();
+        if !validate_email(format!("{}@{}", ascii_local_part, punycode_domain)) {
+            return Err(invalid());
         }
+        let (domain, _) = idna::domain_to_unicode(&punycode_domain);
+        Ok(Self {
+            email: format!("{}@{}", local_part, domain),
+            punycode_email: format!("{}@{}", local_part, punycode_domain)
+        })
+    }
+
+    /// An address read back from `subscriptions`, delivered to the punycode form stored
+    /// with it. Only a row the backfill has not reached yet lacks one; it is derived then.
+    pub fn from_stored(email: String, punycode_email: Option<String>) -> Result<SubscriberEmail, String> {
+        let mut subscriber_email = Self::parse(email)?;
+        if let Some(punycode_email) = punycode_email {
+            subscriber_email.punycode_email = punycode_email;
+        }
+        Ok(subscriber_email)
+    }
+
+    pub fn local_part(&self) -> &str {
+        self.email.rsplit_once('@').expect("A valid email contains an @.").0
+    }
+
+    /// In Unicode.
+    pub fn domain(&self) -> &str {
+        self.email.rsplit_once('@').expect("A valid email contains an @.").1
+    }
+
+    pub fn punycode_domain(&self) -> &str {
+        self.punycode_email.rsplit_once('@').expect("A valid email contains an @.").1
+    }
+
+    /// The form to hand to mail servers.
+    pub fn punycode(&self) -> &str {
+        &self.punycode_email
     }
 }
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
-        &self.0
+        &self.email
     }
 }
 impl std::fmt::Display for SubscriberEmail {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
+        self.email.fmt(f)
     }
 }
 
@@ -30,7 +83,7 @@ mod tests {
     use claim::assert_err;
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
-    use quickcheck::Gen;
+    use quickcheck::{Arbitrary, Gen};
 
     #[test]
     fn empty_string_is_rejected() {
@@ -50,6 +103,61 @@ mod tests {
         assert_err!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn the_domain_is_lowercased_but_not_the_local_part() {
+        let email = SubscriberEmail::parse("Ursula.Le.Guin@Example.COM".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "Ursula.Le.Guin@example.com");
+        assert_eq!(email.local_part(), "Ursula.Le.Guin");
+        assert_eq!(email.domain(), "example.com");
+    }
+
+    #[test]
+    fn an_idn_domain_is_kept_in_unicode_and_in_punycode() {
+        let email = SubscriberEmail::parse("josé@Bücher.de".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "josé@bücher.de");
+        assert_eq!(email.punycode(), "josé@xn--bcher-kva.de");
+        assert_eq!(email.punycode_domain(), "xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn a_stored_address_is_delivered_to_its_stored_punycode_form() {
+        let email = SubscriberEmail::from_stored(
+            "josé@bücher.de".to_string(),
+            Some("josé@xn--bcher-kva.de".to_string())
+        ).unwrap();
+        assert_eq!(email.as_ref(), "josé@bücher.de");
+        assert_eq!(email.punycode(), "josé@xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn a_stored_address_without_a_punycode_form_gets_one() {
+        let email = SubscriberEmail::from_stored("josé@bücher.de".to_string(), None).unwrap();
+        assert_eq!(email.punycode(), "josé@xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn a_punycode_domain_is_displayed_in_unicode() {
+        let email = SubscriberEmail::parse("josé@xn--bcher-kva.de".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "josé@bücher.de");
+    }
+
+    #[test]
+    fn the_local_part_is_normalised() {
+        // `e` followed by a combining acute accent.
+        let decomposed = SubscriberEmail::parse("jose\u{301}@example.com".to_string()).unwrap();
+        assert_eq!(decomposed.as_ref(), "jos\u{e9}@example.com");
+    }
+
+    #[test]
+    fn unicode_whitespace_in_the_local_part_is_rejected() {
+        assert_err!(SubscriberEmail::parse("jos\u{a0}é@example.com".to_string()));
+    }
+
+    #[test]
+    fn an_invalid_idn_domain_is_rejected() {
+        assert_err!(SubscriberEmail::parse("josé@xn--.de".to_string()));
+    }
+
     #[derive(Debug, Clone)]
     struct ValidEmailFixture(pub String);
 
@@ -64,4 +172,29 @@ mod tests {
     fn valid_emails_are_parsed_successfully(valid_email: ValidEmailFixture) -> bool {
         SubscriberEmail::parse(valid_email.0).is_ok()
     }
+
+    #[derive(Debug, Clone)]
+    struct InternationalEmailFixture(pub String);
+
+    impl Arbitrary for InternationalEmailFixture {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let local_parts = ["josé", "用户", "δοκιμή", "пользователь", "ñandú.peña", "ursula"];
+            let domains = ["bücher.de", "例え.jp", "παράδειγμα.δοκιμή", "пример.рф", "München.example", "example.com"];
+            let local_part = local_parts[usize::arbitrary(g) % local_parts.len()];
+            let domain = domains[usize::arbitrary(g) % domains.len()];
+            Self(format!("{}{}@{}", local_part, u16::arbitrary(g), domain))
+        }
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn international_emails_are_parsed_successfully(email: InternationalEmailFixture) -> bool {
+        SubscriberEmail::parse(email.0).is_ok()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn the_punycode_form_is_the_same_address(email: InternationalEmailFixture) -> bool {
+        let email = SubscriberEmail::parse(email.0).unwrap();
+        let reparsed = SubscriberEmail::parse(email.punycode().to_string()).unwrap();
+        email.punycode_domain().is_ascii() && reparsed.as_ref() == email.as_ref()
+    }
 }
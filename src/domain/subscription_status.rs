@@ -0,0 +1,140 @@
+/// Where a subscriber stands, stored as the `subscription_status` Postgres enum.
+/// List memberships use the same type, though only the first three apply to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, serde::Serialize)]
+#[sqlx(type_name = "subscription_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    Unsubscribed,
+    /// The address no longer receives email.
+    Bounced,
+    /// The subscriber marked one of our emails as spam.
+    Complained
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("A subscriber cannot go from {from} to {to}.")]
+pub struct InvalidStatusTransition {
+    pub from: SubscriptionStatus,
+    pub to: SubscriptionStatus
+}
+
+impl SubscriptionStatus {
+    pub const ALL: [Self; 5] = [
+        Self::PendingConfirmation,
+        Self::Confirmed,
+        Self::Unsubscribed,
+        Self::Bounced,
+        Self::Complained
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::PendingConfirmation => "pending_confirmation",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+            Self::Bounced => "bounced",
+            Self::Complained => "complained"
+        }
+    }
+
+    /// Confirmation comes first; once an address has left, only signing up again
+    /// (see `resubscribed`) brings it back. Staying put is not a transition.
+    pub fn can_transition_to(self, to: Self) -> bool {
+        use SubscriptionStatus::*;
+        matches!(
+            (self, to),
+            (PendingConfirmation, Confirmed | Unsubscribed | Bounced | Complained)
+                | (Confirmed, Unsubscribed | Bounced | Complained)
+                | (Unsubscribed | Bounced, PendingConfirmation)
+        )
+    }
+
+    pub fn transition_to(self, to: Self) -> Result<Self, InvalidStatusTransition> {
+        if self.can_transition_to(to) {
+            Ok(to)
+        } else {
+            Err(InvalidStatusTransition { from: self, to })
+        }
+    }
+
+    /// Where a known address ends up when it signs up again: unsubscribed and
+    /// bounced addresses go through double opt-in anew, while those that complained
+    /// are never emailed again, so their signup is ignored (`None`).
+    pub fn resubscribed(self) -> Option<Self> {
+        match self {
+            Self::PendingConfirmation | Self::Unsubscribed | Self::Bounced => Some(Self::PendingConfirmation),
+            Self::Confirmed => Some(Self::Confirmed),
+            Self::Complained => None
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for SubscriptionStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid subscription status.", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionStatus::{self, *};
+    use claim::{assert_err, assert_none, assert_ok_eq, assert_some_eq};
+
+    #[test]
+    fn a_subscriber_is_confirmed_before_leaving() {
+        assert_ok_eq!(PendingConfirmation.transition_to(Confirmed), Confirmed);
+        for to in [Unsubscribed, Bounced, Complained] {
+            assert_ok_eq!(Confirmed.transition_to(to), to);
+        }
+        assert_err!(Confirmed.transition_to(PendingConfirmation));
+    }
+
+    #[test]
+    fn a_subscriber_who_left_cannot_be_confirmed_directly() {
+        for from in [Unsubscribed, Bounced, Complained] {
+            assert_err!(from.transition_to(Confirmed));
+        }
+    }
+
+    #[test]
+    fn staying_put_is_not_a_transition() {
+        for status in SubscriptionStatus::ALL {
+            assert_err!(status.transition_to(status));
+        }
+    }
+
+    #[test]
+    fn unsubscribed_and_bounced_addresses_start_over_when_signing_up_again() {
+        assert_some_eq!(Unsubscribed.resubscribed(), PendingConfirmation);
+        assert_some_eq!(Bounced.resubscribed(), PendingConfirmation);
+        assert_some_eq!(PendingConfirmation.resubscribed(), PendingConfirmation);
+        assert_some_eq!(Confirmed.resubscribed(), Confirmed);
+    }
+
+    #[test]
+    fn addresses_that_complained_cannot_sign_up_again() {
+        assert_none!(Complained.resubscribed());
+        assert_err!(Complained.transition_to(PendingConfirmation));
+    }
+
+    #[test]
+    fn statuses_round_trip_through_their_names() {
+        for status in SubscriptionStatus::ALL {
+            assert_eq!(status.as_str().parse::<SubscriptionStatus>(), Ok(status));
+        }
+        assert_err!("bogus".parse::<SubscriptionStatus>());
+    }
+}
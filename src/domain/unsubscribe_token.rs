@@ -0,0 +1,80 @@
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Signed token that lets a subscriber leave the list without logging in.
+/// It is an HMAC of the subscriber id, so it never needs to be stored.
+#[derive(Debug)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    pub fn generate(subscriber_id: Uuid, secret: &Secret<String>) -> Self {
+        let tag = mac(subscriber_id, secret).finalize().into_bytes();
+        Self(hex::encode(tag))
+    }
+
+    pub fn verify(
+        token: &str,
+        subscriber_id: Uuid,
+        secret: &Secret<String>
+    ) -> Result<(), anyhow::Error> {
+        let tag = hex::decode(token).context("The unsubscribe token is not valid hex.")?;
+        mac(subscriber_id, secret)
+            .verify_slice(&tag)
+            .context("The unsubscribe token does not match the subscriber.")?;
+        Ok(())
+    }
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+fn mac(subscriber_id: Uuid, secret: &Secret<String>) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size.");
+    mac.update(b"unsubscribe:");
+    mac.update(subscriber_id.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    #[test]
+    fn a_generated_token_is_valid_for_its_subscriber() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &secret());
+        assert_ok!(UnsubscribeToken::verify(token.as_ref(), subscriber_id, &secret()));
+    }
+
+    #[test]
+    fn a_token_is_rejected_for_a_different_subscriber() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        assert_err!(UnsubscribeToken::verify(token.as_ref(), Uuid::new_v4(), &secret()));
+    }
+
+    #[test]
+    fn a_token_signed_with_a_different_secret_is_rejected() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &Secret::new("another-key".into()));
+        assert_err!(UnsubscribeToken::verify(token.as_ref(), subscriber_id, &secret()));
+    }
+
+    #[test]
+    fn a_malformed_token_is_rejected() {
+        assert_err!(UnsubscribeToken::verify("not-hex", Uuid::new_v4(), &secret()));
+    }
+}
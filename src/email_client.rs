@@ -18,6 +18,16 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
+}
+
+/// A custom header attached to an outgoing email, e.g. `List-Unsubscribe`.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
 }
 
 impl EmailClient {
@@ -42,14 +52,28 @@ impl EmailClient {
         subject: &str,
         html_content: &str,
         text_content: &str,
+    ) -> Result<(), reqwest::Error> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    pub async fn send_email_with_headers(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
     ) -> Result<(), reqwest::Error> {
         let url = format!("{}/email", self.base_url);
+        // IDNs are handed over in punycode, which every mail server understands.
         let request_body = SendEmailRequest {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
+            from: self.sender.punycode(),
+            to: recipient.punycode(),
             subject,
             html_body: html_content,
             text_body: text_content,
+            headers,
         };
 
         self.http_client
@@ -70,7 +94,7 @@ impl EmailClient {
 #[cfg(test)]
 mod tests {
     use crate::domain::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{EmailClient, EmailHeader};
     use claim::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
@@ -141,6 +165,71 @@ mod tests {
         assert_ok!(outcome)
     }
 
+    struct HeadersMatcher;
+    impl wiremock::Match for HeadersMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["Headers"] == serde_json::json!([{"Name": "X-Custom", "Value": "value"}])
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_includes_them_in_the_request() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(HeadersMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let headers = [EmailHeader {
+            name: "X-Custom".into(),
+            value: "value".into(),
+        }];
+
+        // act
+        let outcome = email_client
+            .send_email_with_headers(&email(), &subject(), &content(), &content(), &headers)
+            .await;
+
+        // assert
+        assert_ok!(outcome);
+    }
+
+    struct RecipientMatcher(&'static str);
+    impl wiremock::Match for RecipientMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["To"] == self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_addresses_idn_domains_in_punycode() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(RecipientMatcher("josé@xn--bcher-kva.de"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let recipient = SubscriberEmail::parse("josé@bücher.de".into()).unwrap();
+
+        // act
+        let outcome = email_client
+            .send_email(&recipient, &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert_ok!(outcome);
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_the_server_returns_500() {
         // arrange
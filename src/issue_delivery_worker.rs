@@ -1,7 +1,13 @@
 pub use crate::{configuration::Settings, startup::get_connection_pool};
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::confirmation_email::try_send_confirmation_email;
+use crate::domain::{IssueTemplate, SubscriberAttributes, SubscriberEmail, SubscriptionStatus, TemplateContext};
+use crate::email_client::{EmailClient, EmailHeader};
+use crate::routes::{one_click_unsubscribe_link, unsubscribe_link};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::welcome_email::try_send_welcome_email;
 use std::time::Duration;
+use sqlx::postgres::{PgArguments, PgRow};
+use sqlx::query::Query;
 use sqlx::{PgPool, Postgres, Transaction};
 use tracing::{field::display, Span};
 use uuid::Uuid;
@@ -21,24 +27,57 @@ pub enum ExecutionOutcome {
 )]
 pub async fn try_execute_task(
     pool: &PgPool,
-    email_client: &EmailClient
+    email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
+    // Confirmation and welcome emails go first: each one is a single email, expected right away.
+    // A failure on that side must not hold up the delivery of issues.
+    if completed_a_task(try_send_confirmation_email(pool, email_client, base_url).await, "confirmation email") {
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    if completed_a_task(try_send_welcome_email(pool, email_client, base_url, hmac_secret).await, "welcome email") {
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let task = dequeue_task::<IssueDeliveryTask>(pool).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue)
     }
     
-    let (transaction, issue_id, email) = task.unwrap();    
+    let (mut transaction, task) = task.unwrap();
+    let issue_id = task.newsletter_issue_id;
+    let email = &task.subscriber_email;
     
     Span::current()
         .record("newsletter_issue_id", &display(issue_id))
         .record("subscriber_email", &display(&email));
     
-        match SubscriberEmail::parse(email.clone()) {
+    // The subscriber may have left the list after the issue was enqueued.
+    match get_confirmed_subscriber(pool, email).await? {
+        Some(subscriber) => match SubscriberEmail::from_stored(email.clone(), subscriber.punycode_email) {
             Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(&email, &issue.title, &issue.html_content, &issue.text_content)
+                let issue = get_issue(pool, issue_id).await?;
+                let context = TemplateContext {
+                    name: &subscriber.name,
+                    email: email.as_ref(),
+                    attributes: &subscriber.attributes
+                };
+                let content = render_newsletter_email(
+                    &issue.html_content,
+                    &issue.text_content,
+                    &context,
+                    subscriber.id,
+                    base_url,
+                    hmac_secret
+                );
+                if let Err(e) = email_client
+                    .send_email_with_headers(
+                        &email,
+                        &issue.title,
+                        &content.html_content,
+                        &content.text_content,
+                        &content.headers
+                    )
                     .await
                 {
                     tracing::error!(
@@ -47,7 +86,9 @@ pub async fn try_execute_task(
                         "Failed to deliver issue to a confirmed subscriber. \
                         Skipping."
                     );
-                }                    
+                } else {
+                    record_delivery(&mut transaction, issue_id, subscriber.id).await?;
+                }
             }
             Err(e) => {
                 tracing::error!(
@@ -57,64 +98,209 @@ pub async fn try_execute_task(
                     Their stored contact details are invalid"
                 );
             }
-            }
+        },
+        None => {
+            tracing::info!("Skipping a subscriber who is no longer confirmed.");
+        }
+    }
 
-    delete_task(transaction, issue_id, &email).await?;    
+    delete_task(transaction, &task).await?;
 
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
-type PgTransaction = Transaction<'static, Postgres>;
-type TaskResponse = Option<(PgTransaction, Uuid, String)>;
+/// Logs a failure instead of handing it back, so the next queue still gets its turn.
+fn completed_a_task(outcome: Result<ExecutionOutcome, anyhow::Error>, queue: &str) -> bool {
+    match outcome {
+        Ok(ExecutionOutcome::TaskCompleted) => true,
+        Ok(ExecutionOutcome::EmptyQueue) => false,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to process the {} queue. Moving on.",
+                queue
+            );
+            false
+        }
+    }
+}
+
+/// What the newsletter sends a subscriber: its bodies rendered for them,
+/// followed by a link to unsubscribe, and the one-click unsubscribe headers.
+pub(crate) struct NewsletterEmail {
+    pub html_content: String,
+    pub text_content: String,
+    pub headers: [EmailHeader; 2]
+}
 
-#[tracing::instrument(skip_all)]
-async fn dequeue_task(
+pub(crate) fn render_newsletter_email(
+    html_content: &str,
+    text_content: &str,
+    context: &TemplateContext,
+    subscriber_id: Uuid,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret
+) -> NewsletterEmail {
+    let link = unsubscribe_link(&base_url.0, subscriber_id, &hmac_secret.0);
+    NewsletterEmail {
+        html_content: format!(
+            "{}<p><a href=\"{}\">Unsubscribe</a> from this newsletter.</p>",
+            render(html_content, |t| t.render_html(context)), link
+        ),
+        text_content: format!(
+            "{}\n\nUnsubscribe from this newsletter: {}",
+            render(text_content, |t| t.render_text(context)), link
+        ),
+        headers: list_unsubscribe_headers(
+            one_click_unsubscribe_link(&base_url.0, subscriber_id, &hmac_secret.0)
+        )
+    }
+}
+
+/// Expands the placeholders of an issue body for one recipient.
+/// Issues published before templating existed may not parse: they are sent as they are.
+fn render(content: &str, render_template: impl Fn(&IssueTemplate) -> String) -> String {
+    match IssueTemplate::parse(content) {
+        Ok(template) => render_template(&template),
+        Err(e) => {
+            tracing::warn!(
+                error.message = %e,
+                "The issue content is not a valid template. Sending it verbatim."
+            );
+            content.to_string()
+        }
+    }
+}
+
+/// Headers required by RFC 2369 and RFC 8058 for one-click unsubscribe.
+fn list_unsubscribe_headers(one_click_link: String) -> [EmailHeader; 2] {
+    [
+        EmailHeader {
+            name: "List-Unsubscribe".into(),
+            value: format!("<{}>", one_click_link)
+        },
+        EmailHeader {
+            name: "List-Unsubscribe-Post".into(),
+            value: "List-Unsubscribe=One-Click".into()
+        }
+    ]
+}
+
+pub(crate) type PgTransaction = Transaction<'static, Postgres>;
+
+/// A row of one of the queues drained by the worker.
+pub(crate) trait QueuedTask: for<'r> sqlx::FromRow<'r, PgRow> + Send + Unpin {
+    const QUEUE: &'static str;
+    /// The columns that identify a task, in the order `bind_key` binds them.
+    const KEY: &'static [&'static str];
+
+    fn bind_key<'q>(&'q self, query: Query<'q, Postgres, PgArguments>) -> Query<'q, Postgres, PgArguments>;
+}
+
+/// The task stays locked, and hidden from other workers, until the
+/// transaction is handed back to `delete_task`.
+#[tracing::instrument(skip_all, fields(queue = T::QUEUE))]
+pub(crate) async fn dequeue_task<T: QueuedTask>(
     pool: &PgPool
-) -> Result<TaskResponse, anyhow::Error> {
+) -> Result<Option<(PgTransaction, T)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let r = sqlx::query!(
-        r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
-        SKIP LOCKED
-        LIMIT 1
-        "#
-    )
-    .fetch_optional(&mut transaction)
-    .await?;
+    let query = format!(
+        "SELECT {} FROM {} FOR UPDATE SKIP LOCKED LIMIT 1",
+        T::KEY.join(", "),
+        T::QUEUE
+    );
+    let task = sqlx::query_as::<_, T>(&query)
+        .fetch_optional(&mut transaction)
+        .await?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(skip_all, fields(queue = T::QUEUE))]
+pub(crate) async fn delete_task<T: QueuedTask>(
+    mut transaction: PgTransaction,
+    task: &T
+) -> Result<(), anyhow::Error> {
+    let key = T::KEY
+        .iter()
+        .enumerate()
+        .map(|(i, column)| format!("{} = ${}", column, i + 1))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+    let query = format!("DELETE FROM {} WHERE {}", T::QUEUE, key);
+    task.bind_key(sqlx::query(&query))
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct IssueDeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String
+}
+impl QueuedTask for IssueDeliveryTask {
+    const QUEUE: &'static str = "issue_delivery_queue";
+    const KEY: &'static [&'static str] = &["newsletter_issue_id", "subscriber_email"];
 
-    if let Some(r) = r {
-        Ok(Some((
-            transaction, r.newsletter_issue_id, r.subscriber_email
-        )))
-    } else {
-        Ok(None)
+    fn bind_key<'q>(&'q self, query: Query<'q, Postgres, PgArguments>) -> Query<'q, Postgres, PgArguments> {
+        query.bind(self.newsletter_issue_id).bind(self.subscriber_email.as_str())
     }
 }
 
+/// Kept so that a subscriber can be told which issues they received.
 #[tracing::instrument(skip_all)]
-async fn delete_task(
-    mut transaction: PgTransaction,
+async fn record_delivery(
+    transaction: &mut PgTransaction,
     issue_id: Uuid,
-    email: &str
+    subscriber_id: Uuid
 ) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
-        DELETE FROM issue_delivery_queue
-        WHERE
-            newsletter_issue_id = $1 AND
-            subscriber_email = $2
+        INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
         "#,
         issue_id,
-        email
+        subscriber_id
     )
-    .execute(&mut transaction)
+    .execute(transaction)
     .await?;
-    transaction.commit().await?;
     Ok(())
 }
 
+struct ConfirmedSubscriber {
+    id: Uuid,
+    punycode_email: Option<String>,
+    name: String,
+    attributes: SubscriberAttributes
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscriber(
+    pool: &PgPool,
+    email: &str
+) -> Result<Option<ConfirmedSubscriber>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT id, punycode_email, name, attributes
+        FROM subscriptions
+        WHERE email = $1 AND status = $2
+        "#,
+        email,
+        SubscriptionStatus::Confirmed as SubscriptionStatus
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(r.map(|r| ConfirmedSubscriber {
+        id: r.id,
+        punycode_email: r.punycode_email,
+        name: r.name,
+        attributes: SubscriberAttributes::from_json(r.attributes)
+    }))
+}
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
@@ -144,11 +330,13 @@ async fn get_issue(
 
 async fn worker_loop(
     pool: PgPool,
-    email_client: EmailClient
+    email_client: EmailClient,
+    base_url: ApplicationBaseUrl,
+    hmac_secret: HmacSecret
 ) -> Result<(), anyhow::Error> {
     loop {
         // TODO: Handle non-transient errors appropriately
-        match try_execute_task(&pool, &email_client).await {
+        match try_execute_task(&pool, &email_client, &base_url, &hmac_secret).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
@@ -165,5 +353,7 @@ pub async fn run_worker_until_stopped(
 ) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);    
     let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    let base_url = ApplicationBaseUrl(configuration.application.base_url);
+    let hmac_secret = HmacSecret(configuration.application.hmac_secret);
+    worker_loop(connection_pool, email_client, base_url, hmac_secret).await
 }
\ No newline at end of file
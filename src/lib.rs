@@ -1,11 +1,17 @@
+pub mod address_backfill;
 pub mod authentication;
+pub mod client_ip;
 pub mod configuration;
+pub mod confirmation_email;
 pub mod domain;
 pub mod email_client;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod mx_check;
+pub mod rate_limit;
 pub mod routes;
 pub mod session_state;
 pub mod startup;
 pub mod telemetry;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod welcome_email;
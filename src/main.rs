@@ -1,6 +1,7 @@
+use rust2prod::address_backfill::backfill_subscriber_addresses;
 use rust2prod::configuration::get_configuration;
 use rust2prod::issue_delivery_worker::run_worker_until_stopped;
-use rust2prod::startup::Application;
+use rust2prod::startup::{get_connection_pool, Application};
 use rust2prod::telemetry::{get_subscriber, init_subscriber};
 use std::fmt::{Debug, Display};
 use tokio::task::JoinError;
@@ -12,6 +13,11 @@ async fn main() -> anyhow::Result<()> {
 
     // panic if we can't read config
     let configuration = get_configuration().expect("Failed to read configuration.");
+    // Before anything reads or sends to those addresses.
+    backfill_subscriber_addresses(
+        &get_connection_pool(&configuration.database),
+        &configuration.email_policy.policy()?
+    ).await?;
     let application = Application::build(configuration.clone()).await?;
     let application_task = tokio::spawn(application.run_until_stopped());
     
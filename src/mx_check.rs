@@ -0,0 +1,271 @@
+use crate::configuration::MxCheckSettings;
+use rand::{Rng, thread_rng};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+
+const RECORD_TYPE_A: u16 = 1;
+const RECORD_TYPE_MX: u16 = 15;
+const CLASS_IN: u16 = 1;
+const RESPONSE_CODE_NXDOMAIN: u16 = 3;
+/// Past this many entries, expired ones are dropped before adding another.
+const MAX_CACHE_ENTRIES: usize = 10_000;
+
+/// Tells whether a domain can receive email at all, to catch typos like `gmial.com`
+/// before the confirmation email bounces. It asks a single resolver, over UDP, for
+/// MX records and, failing those, for the A record mail servers fall back to.
+/// There is no TCP fallback: an answer too long for a datagram counts as a failed lookup.
+pub struct MxChecker {
+    enabled: bool,
+    resolver: SocketAddr,
+    timeout: Duration,
+    fail_open: bool,
+    cache_ttl: Duration,
+    /// Answers by punycode domain, with when they were given.
+    cache: Mutex<HashMap<String, (bool, Instant)>>
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MxCheckError {
+    #[error("{0} does not accept email. Please check the address for typos.")]
+    NoMailServer(String),
+    #[error("We could not check that {0} accepts email. Please try again later.")]
+    Unverified(String)
+}
+
+#[derive(thiserror::Error, Debug)]
+enum LookupError {
+    #[error("The DNS resolver did not answer in time.")]
+    Timeout,
+    #[error("The DNS resolver could not be reached.")]
+    Io(#[from] std::io::Error),
+    #[error("The DNS resolver sent back an answer we could not use: {0}")]
+    BadResponse(&'static str)
+}
+
+impl MxChecker {
+    pub fn new(settings: &MxCheckSettings) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            enabled: settings.enabled,
+            resolver: settings.resolver.parse()?,
+            timeout: Duration::from_millis(settings.timeout_milliseconds),
+            fail_open: settings.fail_open,
+            cache_ttl: Duration::from_secs(settings.cache_ttl_seconds),
+            cache: Mutex::new(HashMap::new())
+        })
+    }
+
+    /// `domain` is shown in errors; `punycode_domain` is what gets looked up.
+    #[tracing::instrument(name = "Check that a domain accepts email", skip(self))]
+    pub async fn check(&self, domain: &str, punycode_domain: &str) -> Result<(), MxCheckError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let accepts_email = match self.cached(punycode_domain) {
+            Some(accepts_email) => accepts_email,
+            None => match self.accepts_email(punycode_domain).await {
+                Ok(accepts_email) => {
+                    self.remember(punycode_domain, accepts_email);
+                    accepts_email
+                },
+                Err(e) => {
+                    tracing::warn!(error.cause_chain = ?e, fail_open = self.fail_open, "The MX check could not be completed.");
+                    if self.fail_open {
+                        return Ok(());
+                    }
+                    return Err(MxCheckError::Unverified(domain.into()));
+                }
+            }
+        };
+        if accepts_email {
+            Ok(())
+        } else {
+            Err(MxCheckError::NoMailServer(domain.into()))
+        }
+    }
+
+    fn cached(&self, domain: &str) -> Option<bool> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(domain)
+            .filter(|(_, at)| at.elapsed() < self.cache_ttl)
+            .map(|(accepts_email, _)| *accepts_email)
+    }
+
+    fn remember(&self, domain: &str, accepts_email: bool) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            let cache_ttl = self.cache_ttl;
+            cache.retain(|_, (_, at)| at.elapsed() < cache_ttl);
+        }
+        cache.insert(domain.to_owned(), (accepts_email, Instant::now()));
+    }
+
+    async fn accepts_email(&self, domain: &str) -> Result<bool, LookupError> {
+        // A domain without MX records takes mail on its A record (RFC 5321, 5.1).
+        for record_type in [RECORD_TYPE_MX, RECORD_TYPE_A] {
+            match self.count_records(domain, record_type).await? {
+                Some(0) => continue,
+                Some(_) => return Ok(true),
+                None => return Ok(false)
+            }
+        }
+        Ok(false)
+    }
+
+    /// `None` if the domain does not exist.
+    async fn count_records(&self, domain: &str, record_type: u16) -> Result<Option<usize>, LookupError> {
+        let id: u16 = thread_rng().gen();
+        let query = encode_query(id, domain, record_type)?;
+        let socket = UdpSocket::bind(if self.resolver.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).await?;
+        socket.connect(self.resolver).await?;
+        let exchange = async {
+            socket.send(&query).await?;
+            let mut buffer = [0u8; 1232];
+            loop {
+                let n = socket.recv(&mut buffer).await?;
+                // Stray datagrams, e.g. late answers to an earlier query, are not ours.
+                if n >= 2 && u16::from_be_bytes([buffer[0], buffer[1]]) == id {
+                    return decode_answer_count(&buffer[..n], record_type);
+                }
+            }
+        };
+        tokio::time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| LookupError::Timeout)?
+    }
+}
+
+fn encode_query(id: u16, domain: &str, record_type: u16) -> Result<Vec<u8>, LookupError> {
+    let mut query = Vec::with_capacity(18 + domain.len());
+    query.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired; one question.
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in domain.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(LookupError::BadResponse("the domain has an invalid label"));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&record_type.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+/// Counts the answers of `record_type`, so that a CNAME alone does not count.
+fn decode_answer_count(message: &[u8], record_type: u16) -> Result<Option<usize>, LookupError> {
+    let read_u16 = |at: usize| {
+        message
+            .get(at..at + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .ok_or(LookupError::BadResponse("the message is truncated"))
+    };
+    let flags = read_u16(2)?;
+    if flags & 0x8000 == 0 {
+        return Err(LookupError::BadResponse("the message is not a response"));
+    }
+    // TC: whatever records made it into the datagram are not all of them.
+    if flags & 0x0200 != 0 {
+        return Err(LookupError::BadResponse("the answer was truncated"));
+    }
+    match flags & 0x000f {
+        0 => {},
+        RESPONSE_CODE_NXDOMAIN => return Ok(None),
+        _ => return Err(LookupError::BadResponse("the resolver reported an error"))
+    }
+    let question_count = read_u16(4)?;
+    let answer_count = read_u16(6)?;
+    let mut at = 12;
+    for _ in 0..question_count {
+        at = skip_name(message, at)? + 4;
+    }
+    let mut matching = 0;
+    for _ in 0..answer_count {
+        at = skip_name(message, at)?;
+        if read_u16(at)? == record_type {
+            matching += 1;
+        }
+        let data_length = read_u16(at + 8)? as usize;
+        at += 10 + data_length;
+    }
+    Ok(Some(matching))
+}
+
+fn skip_name(message: &[u8], mut at: usize) -> Result<usize, LookupError> {
+    loop {
+        let length = *message.get(at).ok_or(LookupError::BadResponse("a name is truncated"))?;
+        match length {
+            0 => return Ok(at + 1),
+            // A compression pointer ends the name.
+            length if length & 0xc0 == 0xc0 => return Ok(at + 2),
+            length => at += 1 + length as usize
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_answer_count, encode_query, RECORD_TYPE_A, RECORD_TYPE_MX};
+    use claim::{assert_err, assert_matches};
+
+    /// A response to `query` carrying answers of the given types, each with a compressed name.
+    fn response(query: &[u8], response_code: u8, answer_types: &[u16]) -> Vec<u8> {
+        let mut message = query.to_vec();
+        message[2] |= 0x80;
+        message[3] = 0x80 | response_code;
+        message[7] = answer_types.len() as u8;
+        for answer_type in answer_types {
+            message.extend_from_slice(&[0xc0, 12]);
+            message.extend_from_slice(&answer_type.to_be_bytes());
+            message.extend_from_slice(&[0, 1, 0, 0, 0x0e, 0x10, 0, 4, 127, 0, 0, 1]);
+        }
+        message
+    }
+
+    #[test]
+    fn a_query_encodes_the_domain_as_labels() {
+        let query = encode_query(0x1234, "example.com", RECORD_TYPE_MX).unwrap();
+        assert_eq!(&query[..2], &[0x12, 0x34]);
+        assert_eq!(&query[12..], b"\x07example\x03com\x00\x00\x0f\x00\x01");
+    }
+
+    #[test]
+    fn only_answers_of_the_requested_type_are_counted() {
+        let query = encode_query(1, "example.com", RECORD_TYPE_MX).unwrap();
+        // A CNAME (5) followed by two MX records.
+        let message = response(&query, 0, &[5, RECORD_TYPE_MX, RECORD_TYPE_MX]);
+        assert_matches!(decode_answer_count(&message, RECORD_TYPE_MX), Ok(Some(2)));
+        assert_matches!(decode_answer_count(&message, RECORD_TYPE_A), Ok(Some(0)));
+    }
+
+    #[test]
+    fn a_missing_domain_has_no_records() {
+        let query = encode_query(1, "gmial.com", RECORD_TYPE_MX).unwrap();
+        assert_matches!(decode_answer_count(&response(&query, 3, &[]), RECORD_TYPE_MX), Ok(None));
+    }
+
+    #[test]
+    fn a_server_failure_is_an_error() {
+        let query = encode_query(1, "example.com", RECORD_TYPE_MX).unwrap();
+        assert_err!(decode_answer_count(&response(&query, 2, &[]), RECORD_TYPE_MX));
+    }
+
+    #[test]
+    fn an_answer_flagged_as_truncated_is_an_error() {
+        let query = encode_query(1, "example.com", RECORD_TYPE_MX).unwrap();
+        let mut message = response(&query, 0, &[]);
+        message[2] |= 0x02;
+        assert_err!(decode_answer_count(&message, RECORD_TYPE_MX));
+    }
+
+    #[test]
+    fn a_truncated_response_is_an_error() {
+        let query = encode_query(1, "example.com", RECORD_TYPE_MX).unwrap();
+        let message = response(&query, 0, &[RECORD_TYPE_MX]);
+        assert_err!(decode_answer_count(&message[..message.len() - 12], RECORD_TYPE_MX));
+    }
+}
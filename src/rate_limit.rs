@@ -0,0 +1,74 @@
+use crate::configuration::{RateLimit, RateLimitSettings};
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+/// Fixed-window counters in Redis, shared by every instance of the application.
+/// Each endpoint counts separately, with the same limits.
+pub struct RateLimiter {
+    connection: ConnectionManager,
+    settings: RateLimitSettings
+}
+
+/// How long a throttled client has to wait before the window resets.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryAfter(pub u64);
+
+impl std::fmt::Display for RetryAfter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} seconds", self.0)
+    }
+}
+
+impl RateLimiter {
+    pub async fn new(redis_uri: &Secret<String>, settings: RateLimitSettings) -> Result<Self, anyhow::Error> {
+        let connection = redis::Client::open(redis_uri.expose_secret().as_str())?
+            .get_tokio_connection_manager()
+            .await?;
+        Ok(Self { connection, settings })
+    }
+
+    pub async fn check_ip(&self, endpoint: &str, ip_address: &str) -> Result<(), RetryAfter> {
+        self.check(endpoint, "ip", ip_address, &self.settings.per_ip).await
+    }
+
+    /// The address is hashed, so the counters do not hold on to personal data.
+    pub async fn check_email(&self, endpoint: &str, email: &str) -> Result<(), RetryAfter> {
+        let email_hash = hex::encode(Sha256::digest(email.trim().to_lowercase().as_bytes()));
+        self.check(endpoint, "email", &email_hash, &self.settings.per_email).await
+    }
+
+    /// An unreachable Redis lets the request through: the limits guard against
+    /// abuse, they are not worth turning every visitor away for.
+    async fn check(&self, endpoint: &str, scope: &str, key: &str, limit: &RateLimit) -> Result<(), RetryAfter> {
+        match self.hit(endpoint, scope, key, limit).await {
+            Ok(None) => Ok(()),
+            Ok(Some(retry_after)) => {
+                tracing::warn!(endpoint, scope, retry_after = retry_after.0, "A client hit the rate limit.");
+                Err(retry_after)
+            },
+            Err(e) => {
+                tracing::error!(error.cause_chain = ?e, "Failed to check a rate limit.");
+                Ok(())
+            }
+        }
+    }
+
+    /// Counts one request; returns `Some` once the window's allowance is spent.
+    async fn hit(&self, endpoint: &str, scope: &str, key: &str, limit: &RateLimit) -> Result<Option<RetryAfter>, redis::RedisError> {
+        let key = format!("{}:{}:{}:{}", self.settings.key_prefix, endpoint, scope, key);
+        let (count, ttl): (u64, i64) = redis::pipe()
+            .atomic()
+            // Starts the window on the first request only, so that retries do not extend it.
+            .cmd("SET").arg(&key).arg(0).arg("EX").arg(limit.window_seconds).arg("NX").ignore()
+            .incr(&key, 1)
+            .ttl(&key)
+            .query_async(&mut self.connection.clone())
+            .await?;
+        if count > limit.max_requests {
+            Ok(Some(RetryAfter(ttl.max(1) as u64)))
+        } else {
+            Ok(None)
+        }
+    }
+}
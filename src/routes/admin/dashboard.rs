@@ -26,6 +26,11 @@ pub async fn admin_dashboard(
                         <ol>
                             <li><a href="/admin/password">Change password</a></li>
                             <li><a href="/admin/newsletters">Send newsletter</a></li>
+                            <li><a href="/admin/welcome-email">Edit the welcome email</a></li>
+                            <li><a href="/admin/lists">Manage mailing lists</a></li>
+                            <li><a href="/admin/tags">Manage subscriber tags</a></li>
+                            <li><a href="/admin/subscribers">Browse subscribers</a></li>
+                            <li><a href="/admin/subscribers/import">Import subscribers</a></li>
                             <li>
                                 <form name="logoutForm" action="/admin/logout" method="post">
                                     <input type="submit" value="Logout">
@@ -0,0 +1,74 @@
+use crate::utils::e500;
+use std::fmt::Write;
+use actix_web::{HttpResponse, web, http::header::ContentType};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub name: String,
+    pub is_default: bool
+}
+
+pub async fn lists_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut lists_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        let default_marker = if list.is_default { " (default)" } else { "" };
+        writeln!(
+            lists_html,
+            "<li>{}{} <code>{}</code></li>",
+            htmlescape::encode_minimal(&list.name),
+            default_marker,
+            list.list_id
+        ).unwrap();
+    }
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        format!(r#"<!DOCTYPE html><html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Mailing lists</title>
+        </head>
+        <body>
+        {msg_html}
+        <ul>
+            {lists_html}
+        </ul>
+        <form action="/admin/lists" method="post">
+            <label>Name
+                <input type="text" placeholder="Enter the list name" name="name">
+            </label>
+            <button type="submit">Create list</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#)
+    ))
+}
+
+#[tracing::instrument(name = "Get mailing lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT list_id, name, is_default
+        FROM lists
+        ORDER BY created_at, name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve mailing lists.")?;
+    Ok(lists)
+}
@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::{get_lists, lists_form, MailingList};
+pub use post::create_list;
\ No newline at end of file
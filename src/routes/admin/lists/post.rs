@@ -0,0 +1,45 @@
+use crate::utils::{e500, see_other};
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(form, pool))]
+pub async fn create_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The list name cannot be empty.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, name)
+        VALUES ($1, $2)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store a new mailing list.")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_inserted_rows == 0 {
+        FlashMessage::error("A list with this name already exists.").send();
+    } else {
+        FlashMessage::info("The list has been created.").send();
+    }
+    Ok(see_other("/admin/lists"))
+}
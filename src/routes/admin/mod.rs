@@ -1,8 +1,16 @@
 mod dashboard;
+mod lists;
 mod logout;
 mod password;
+mod subscribers;
+mod tags;
 mod newsletter;
+mod welcome_email;
 pub use dashboard::*;
+pub use lists::*;
 pub use logout::log_out;
 pub use password::*;
-pub use newsletter::*;
\ No newline at end of file
+pub use subscribers::*;
+pub use tags::*;
+pub use newsletter::*;
+pub use welcome_email::*;
\ No newline at end of file
@@ -1,13 +1,46 @@
+use crate::routes::admin::lists::get_lists;
+use crate::routes::admin::tags::get_tags;
+use crate::utils::e500;
 use std::fmt::Write;
-use actix_web::{HttpResponse, http::header::ContentType};
+use actix_web::{HttpResponse, web, http::header::ContentType};
+use sqlx::PgPool;
 
 pub async fn get_newsletter_form(
-    flash_messages: actix_web_flash_messages::IncomingFlashMessages
+    flash_messages: actix_web_flash_messages::IncomingFlashMessages,
+    pool: web::Data<PgPool>
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut notification_html = String::new();
     for m in flash_messages.iter() {
         writeln!(notification_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
+
+    let mut lists_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            lists_html,
+            r#"<label><input type="checkbox" name="list_id" value="{}"> {}</label><br>"#,
+            list.list_id,
+            htmlescape::encode_minimal(&list.name)
+        ).unwrap();
+    }
+
+    let mut include_tags_html = String::new();
+    let mut exclude_tags_html = String::new();
+    for tag in get_tags(&pool).await.map_err(e500)? {
+        let name = htmlescape::encode_minimal(&tag.name);
+        writeln!(
+            include_tags_html,
+            r#"<label><input type="checkbox" name="include_tag_id" value="{}"> {}</label><br>"#,
+            tag.tag_id,
+            name
+        ).unwrap();
+        writeln!(
+            exclude_tags_html,
+            r#"<label><input type="checkbox" name="exclude_tag_id" value="{}"> {}</label><br>"#,
+            tag.tag_id,
+            name
+        ).unwrap();
+    }
     
     let idempotency_key = uuid::Uuid::new_v4();
     Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
@@ -18,21 +51,53 @@ pub async fn get_newsletter_form(
         </head>
         <body>
             {notification_html}
-            <form action="/admin/newsletters" method="post">
+            <form id="publish-form" action="/admin/newsletters" method="post">
                 <label>Title
                     <input type="text" placeholder="Welcome to the First Issue!" name="title">
                 </label>
                 <br>
+                <p>Personalise the content with <code>{{{{ name }}}}</code>, <code>{{{{ email }}}}</code>
+                    or <code>{{{{ attributes.company | default("your team") }}}}</code>.</p>
                 <label>HTML Content
-                    <textarea placeholder="<h1>Newsletter content</h1>" name="html_content">
+                    <textarea placeholder="<h1>Newsletter content</h1>" name="html_content"></textarea>
                 </label>
                 <br>
                 <label>Plain Text Content
-                    <textarea placeholder="Newsletter content" name="text_content">
+                    <textarea placeholder="Newsletter content" name="text_content"></textarea>
                 </label>
-                <input hidden type="text" name="idemopotency_key" value="{idempotency_key}">
+                <br>
+                <fieldset>
+                    <legend>Send to</legend>
+                    {lists_html}
+                </fieldset>
+                <fieldset>
+                    <legend>Only subscribers tagged with any of</legend>
+                    {include_tags_html}
+                </fieldset>
+                <fieldset>
+                    <legend>Skip subscribers tagged with</legend>
+                    {exclude_tags_html}
+                </fieldset>
+                <p>Recipients: <span id="recipient-count">0</span></p>
+                <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
                 <button type="submit">Publish</button>
             </form>
+            <script>
+                const form = document.getElementById("publish-form");
+                form.addEventListener("change", async () => {{
+                    const segment = new URLSearchParams();
+                    for (const [key, value] of new FormData(form)) {{
+                        if (key.endsWith("_id")) {{
+                            segment.append(key, value);
+                        }}
+                    }}
+                    const response = await fetch("/admin/newsletters/recipients?" + segment);
+                    if (response.ok) {{
+                        const {{ recipients }} = await response.json();
+                        document.getElementById("recipient-count").textContent = recipients;
+                    }}
+                }});
+            </script>
         </body>
         </html>
         "#)
@@ -1,4 +1,6 @@
 mod get;
 mod post;
-pub use get::*;
-pub use post::*;
\ No newline at end of file
+mod segment;
+pub use get::get_newsletter_form;
+pub use post::{publish_newsletter, PublishError};
+pub use segment::count_newsletter_recipients;
\ No newline at end of file
@@ -1,7 +1,10 @@
 use crate::authentication::UserId;
+use crate::domain::IssueTemplate;
 use crate::idempotency::{IdempotencyKey, NextAction, save_response, try_processing};
 use crate::routes::error_chain_fmt;
+use super::segment::Segment;
 use crate::utils::{e400, e500, see_other};
+use std::collections::HashSet;
 use std::fmt::Formatter;
 use actix_web::body::BoxBody;
 use actix_web::http::header::HeaderValue;
@@ -58,22 +61,43 @@ pub struct FormData {
 
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip (form, pool, user_id),
+    skip (body, pool, user_id),
     fields (user_id = tracing::field::Empty)
 )]
 pub async fn publish_newsletter(
-    form: web::Form<FormData>,
+    body: web::Bytes,
     pool: web::Data<PgPool>,    
     user_id: web::ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     
+    // Several `list_id` and tag checkboxes arrive as repeated keys, which `web::Form` rejects.
+    // `#[serde(flatten)]` buffers a lone key as a string rather than a sequence,
+    // so the segment is read from the same body on its own.
     let FormData {
         title,
         text_content,
         html_content,
         idempotency_key,
-    } = form.0;
+    } = serde_html_form::from_bytes(&body).map_err(e400)?;
+    let segment: Segment = serde_html_form::from_bytes(&body).map_err(e400)?;
+
+    if segment.list_ids.is_empty() {
+        FlashMessage::error("You must choose at least one list to send the issue to.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    // Templates are expanded by the delivery worker: reject broken ones before anything is queued.
+    for (content, label) in [(&html_content, "HTML content"), (&text_content, "plain text content")] {
+        if let Err(e) = IssueTemplate::parse(content) {
+            FlashMessage::error(format!(
+                "The {} is not a valid template: {}",
+                label,
+                htmlescape::encode_minimal(&e)
+            )).send();
+            return Ok(see_other("/admin/newsletters"));
+        }
+    }
+    ensure_segment_targets_exist(&pool, &segment).await?;
 
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
     let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
@@ -87,7 +111,13 @@ pub async fn publish_newsletter(
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        &segment
+    )
         .await
         .context("Failed to store newsletter issue details")
         .map_err(e500)?;
@@ -110,7 +140,8 @@ async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
     text_content: &str,
-    html_content: &str
+    html_content: &str,
+    segment: &Segment
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
@@ -120,14 +151,20 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
-            published_at
+            published_at,
+            list_ids,
+            include_tag_ids,
+            exclude_tag_ids
         )
-        VALUES($1, $2, $3, $4, now())
+        VALUES($1, $2, $3, $4, now(), $5, $6, $7)
         "#,
         newsletter_issue_id,
         title,
         text_content,
-        html_content
+        html_content,
+        &segment.list_ids,
+        &segment.include_tag_ids,
+        &segment.exclude_tag_ids
     )
     .execute(transaction)
     .await?;
@@ -137,7 +174,7 @@ async fn insert_newsletter_issue(
 #[tracing::instrument(skip_all)]
 async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid
+    newsletter_issue_id: Uuid,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
@@ -145,9 +182,10 @@ async fn enqueue_delivery_tasks(
             newsletter_issue_id,
             subscriber_email
         )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        SELECT i.newsletter_issue_id, r.email
+        FROM newsletter_issues i,
+            segment_recipients(i.list_ids, i.include_tag_ids, i.exclude_tag_ids) r
+        WHERE i.newsletter_issue_id = $1
         "#,
         newsletter_issue_id
     )
@@ -156,6 +194,37 @@ async fn enqueue_delivery_tasks(
     Ok(())
 }
 
+#[tracing::instrument(skip_all)]
+async fn ensure_segment_targets_exist(
+    pool: &PgPool,
+    segment: &Segment
+) -> Result<(), actix_web::Error> {
+    let tag_ids = [&segment.include_tag_ids[..], &segment.exclude_tag_ids[..]].concat();
+    let known = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM lists WHERE list_id = ANY($1)) as "lists!",
+            (SELECT COUNT(*) FROM tags WHERE tag_id = ANY($2)) as "tags!"
+        "#,
+        &segment.list_ids,
+        &tag_ids
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(e500)?;
+    if known.lists != count_distinct(&segment.list_ids) {
+        return Err(e400("The issue targets a list that does not exist."));
+    }
+    if known.tags != count_distinct(&tag_ids) {
+        return Err(e400("The issue targets a tag that does not exist."));
+    }
+    Ok(())
+}
+
+fn count_distinct(ids: &[Uuid]) -> i64 {
+    ids.iter().collect::<HashSet<_>>().len() as i64
+}
+
 fn success_message() -> FlashMessage {
     FlashMessage::info("The newsletter issue has been accepted - \
                                 emails will go out shortly.")
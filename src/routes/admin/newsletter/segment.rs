@@ -0,0 +1,51 @@
+use crate::utils::{e400, e500};
+use actix_web::{HttpRequest, HttpResponse, web};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Who an issue goes to: confirmed members of any of `list_ids`, narrowed by tags.
+#[derive(serde::Deserialize)]
+pub struct Segment {
+    #[serde(default, rename = "list_id")]
+    pub list_ids: Vec<Uuid>,
+    /// When non-empty, recipients must carry at least one of these tags.
+    #[serde(default, rename = "include_tag_id")]
+    pub include_tag_ids: Vec<Uuid>,
+    /// Recipients carrying any of these tags are skipped.
+    #[serde(default, rename = "exclude_tag_id")]
+    pub exclude_tag_ids: Vec<Uuid>,
+}
+
+#[derive(serde::Serialize)]
+struct RecipientCount {
+    recipients: i64
+}
+
+/// Backs the live recipient count on the publish form.
+pub async fn count_newsletter_recipients(
+    request: HttpRequest,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let segment: Segment = serde_html_form::from_str(request.query_string()).map_err(e400)?;
+    let recipients = count_recipients(&pool, &segment).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(RecipientCount { recipients }))
+}
+
+#[tracing::instrument(name = "Count the recipients of a segment", skip_all)]
+pub async fn count_recipients(
+    pool: &PgPool,
+    segment: &Segment
+) -> Result<i64, anyhow::Error> {
+    // `segment_recipients` is also what `enqueue_delivery_tasks` reads from.
+    let row = sqlx::query!(
+        r#"SELECT COUNT(*) as "count!" FROM segment_recipients($1, $2, $3)"#,
+        &segment.list_ids,
+        &segment.include_tag_ids,
+        &segment.exclude_tag_ids
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the recipients of a segment.")?;
+    Ok(row.count)
+}
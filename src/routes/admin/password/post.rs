@@ -26,7 +26,7 @@ pub async fn change_password(
     }
 
     let password_length = form.new_password.expose_secret().len();
-    if password_length < 12 || password_length > 128 {
+    if !(12..=128).contains(&password_length) {
         FlashMessage::error(
             "Password must be between 12 and 128 characters."
         ).send();
@@ -45,7 +45,7 @@ pub async fn change_password(
                 FlashMessage::error("The current password is incorrect.").send();
                 Ok(see_other("/admin/password"))
             }
-            AuthError::UnexpectedError(_) => Err(e500(e).into())
+            AuthError::UnexpectedError(_) => Err(e500(e))
         }
     }
     
@@ -0,0 +1,270 @@
+use crate::domain::{EmailPolicy, ErasureTombstone, NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::email_client::EmailClient;
+use crate::routes::consent::ConsentContext;
+use crate::routes::subscription_status::StatusTransitionError;
+use crate::routes::{confirm_subscriber, generate_subscription_token, mark_subscriber_as_unsubscribed};
+use crate::routes::subscriptions::{delete_tokens, send_confirmation_email, store_token};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::utils::{e500, see_other};
+use super::detail::get_subscriber;
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct NameFormData {
+    name: String
+}
+
+fn details_page(subscriber_id: Uuid) -> HttpResponse {
+    see_other(&format!("/admin/subscribers/{}", subscriber_id))
+}
+
+fn unknown_subscriber() -> HttpResponse {
+    FlashMessage::error("The subscriber does not exist.").send();
+    see_other("/admin/subscribers")
+}
+
+#[tracing::instrument(name = "Admin: confirm a subscriber", skip(request, pool))]
+pub async fn force_confirm_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = match get_subscriber(&pool, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(unknown_subscriber())
+    };
+    // Someone who left must sign up again: an admin cannot opt them back in.
+    if subscriber.status != SubscriptionStatus::PendingConfirmation {
+        FlashMessage::error("Only subscribers awaiting confirmation can be confirmed.").send();
+        return Ok(details_page(subscriber_id));
+    }
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let consent = ConsentContext::from_request(&request, "admin", None);
+    match confirm_subscriber(&mut transaction, subscriber_id, &consent).await {
+        Ok(()) => {},
+        Err(StatusTransitionError::Invalid(e)) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(details_page(subscriber_id));
+        },
+        Err(e) => return Err(e500(anyhow::Error::new(e).context("Failed to mark the subscriber as confirmed.")))
+    }
+    delete_tokens(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to delete pending confirmation tokens.")
+        .map_err(e500)?;
+    transaction.commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")
+        .map_err(e500)?;
+    FlashMessage::info("The subscriber has been confirmed.").send();
+    Ok(details_page(subscriber_id))
+}
+
+#[tracing::instrument(name = "Admin: unsubscribe a subscriber", skip(pool))]
+pub async fn admin_unsubscribe_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    if get_subscriber(&pool, subscriber_id).await.map_err(e500)?.is_none() {
+        return Ok(unknown_subscriber());
+    }
+    mark_subscriber_as_unsubscribed(&pool, subscriber_id, "admin")
+        .await
+        .context("Failed to unsubscribe a subscriber.")
+        .map_err(e500)?;
+    FlashMessage::info("The subscriber has been unsubscribed.").send();
+    Ok(details_page(subscriber_id))
+}
+
+#[tracing::instrument(
+    name = "Admin: resend a confirmation email",
+    skip(pool, email_client, base_url)
+)]
+pub async fn admin_resend_confirmation(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = match get_subscriber(&pool, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => return Ok(unknown_subscriber())
+    };
+    if subscriber.status != SubscriptionStatus::PendingConfirmation {
+        FlashMessage::error("Only subscribers awaiting confirmation can be sent a confirmation email.").send();
+        return Ok(details_page(subscriber_id));
+    }
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::from_stored(subscriber.email, subscriber.punycode_email).map_err(e500)?,
+        name: SubscriberName::parse(subscriber.name).map_err(e500)?,
+        attributes: SubscriberAttributes::from_json(subscriber.attributes)
+    };
+
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    delete_tokens(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to delete stale confirmation tokens.")
+        .map_err(e500)?;
+    let subscription_token = generate_subscription_token();
+    store_token(&mut transaction, subscriber_id, &subscription_token)
+        .await
+        .context("Failed to store a new confirmation token.")
+        .map_err(e500)?;
+    transaction.commit()
+        .await
+        .context("Failed to commit SQL transaction to rotate a confirmation token.")
+        .map_err(e500)?;
+
+    if let Err(e) = send_confirmation_email(&email_client, new_subscriber, &base_url.0, &subscription_token).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to resend a confirmation email."
+        );
+        FlashMessage::error("The confirmation email could not be sent. Please try again later.").send();
+        return Ok(details_page(subscriber_id));
+    }
+    FlashMessage::info("A new confirmation email has been sent.").send();
+    Ok(details_page(subscriber_id))
+}
+
+#[tracing::instrument(name = "Admin: rename a subscriber", skip(form, pool))]
+pub async fn rename_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<NameFormData>,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let name = match SubscriberName::parse(form.0.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(details_page(subscriber_id));
+        }
+    };
+    let n_updated_rows = sqlx::query!(
+        r#"UPDATE subscriptions SET name = $2 WHERE id = $1"#,
+        subscriber_id,
+        name.as_ref()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to rename a subscriber.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated_rows == 0 {
+        return Ok(unknown_subscriber());
+    }
+    FlashMessage::info("The name has been updated.").send();
+    Ok(details_page(subscriber_id))
+}
+
+/// Right to erasure. Everything about the subscriber goes in one transaction;
+/// only a tombstone of their address is kept, so that an import cannot add them back.
+#[tracing::instrument(name = "Admin: erase a subscriber", skip(pool, email_policy, hmac_secret))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_policy: web::Data<EmailPolicy>,
+    hmac_secret: web::Data<HmacSecret>
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let is_erased = erase_subscriber(&mut transaction, subscriber_id, &email_policy, &hmac_secret.0)
+        .await
+        .context("Failed to erase a subscriber.")
+        .map_err(e500)?;
+    transaction.commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")
+        .map_err(e500)?;
+
+    if !is_erased {
+        return Ok(unknown_subscriber());
+    }
+    FlashMessage::info("The subscriber has been deleted.").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+/// Returns `false` if there is no such subscriber.
+#[tracing::instrument(name = "Erase a subscriber from every table", skip(transaction, email_policy, hmac_secret))]
+async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email_policy: &EmailPolicy,
+    hmac_secret: &Secret<String>
+) -> Result<bool, sqlx::Error> {
+    let email = match sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    {
+        Some(r) => r.email,
+        None => return Ok(false)
+    };
+    delete_tokens(transaction, subscriber_id).await?;
+    sqlx::query!(r#"DELETE FROM list_memberships WHERE subscriber_id = $1"#, subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1"#, subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(r#"DELETE FROM issue_deliveries WHERE subscriber_id = $1"#, subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(r#"DELETE FROM consent_events WHERE subscriber_id = $1"#, subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(r#"DELETE FROM subscription_status_history WHERE subscriber_id = $1"#, subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(r#"DELETE FROM welcome_email_queue WHERE subscriber_id = $1"#, subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(r#"DELETE FROM confirmation_email_queue WHERE subscriber_id = $1"#, subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    // Queued deliveries refer to the address, not to the subscriber id.
+    sqlx::query!(r#"DELETE FROM issue_delivery_queue WHERE subscriber_email = $1"#, email)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    // Keyed on the canonical address, so that no alias of the mailbox can be imported back.
+    let canonical_email = match SubscriberEmail::parse(email.clone()) {
+        Ok(email) => email_policy.canonical(&email),
+        Err(_) => email
+    };
+    let tombstone = ErasureTombstone::compute(&canonical_email, hmac_secret);
+    sqlx::query!(
+        r#"
+        INSERT INTO erasure_tombstones (email_hash)
+        VALUES ($1)
+        ON CONFLICT (email_hash) DO UPDATE SET erased_at = now()
+        "#,
+        tombstone.as_ref()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(true)
+}
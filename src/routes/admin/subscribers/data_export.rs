@@ -0,0 +1,56 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::routes::{data_export_response, get_data_export, send_data_export_email};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::utils::{e404, e500, see_other};
+use super::detail::get_subscriber;
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[tracing::instrument(name = "Admin: download the data of a subscriber", skip(pool))]
+pub async fn download_subscriber_data(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let export = get_data_export(&pool, subscriber_id.into_inner())
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("The subscriber does not exist."))?;
+    Ok(data_export_response(&export))
+}
+
+/// Sends the subscriber the same verified download link they would get by asking themselves.
+#[tracing::instrument(
+    name = "Admin: send a data export link",
+    skip(pool, email_client, base_url, hmac_secret)
+)]
+pub async fn send_subscriber_data_export(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = match get_subscriber(&pool, subscriber_id).await.map_err(e500)? {
+        Some(subscriber) => subscriber,
+        None => {
+            FlashMessage::error("The subscriber does not exist.").send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+    let email = SubscriberEmail::from_stored(subscriber.email, subscriber.punycode_email).map_err(e500)?;
+    if let Err(e) = send_data_export_email(&email_client, &email, &base_url.0, subscriber_id, &hmac_secret.0).await {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send a data export link."
+        );
+        FlashMessage::error("The data export email could not be sent. Please try again later.").send();
+    } else {
+        FlashMessage::info("The subscriber has been sent a link to download their data.").send();
+    }
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
@@ -0,0 +1,239 @@
+use crate::domain::SubscriptionStatus;
+use crate::routes::consent::get_consent_events;
+use crate::routes::subscription_status::get_status_history;
+use crate::utils::{e404, e500};
+use std::fmt::Write;
+use actix_web::{HttpResponse, web, http::header::ContentType};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct SubscriberDetails {
+    pub id: Uuid,
+    pub email: String,
+    pub punycode_email: Option<String>,
+    pub name: String,
+    pub status: SubscriptionStatus,
+    pub subscribed_at: DateTime<Utc>,
+    pub signup_source: Option<String>,
+    pub attributes: serde_json::Value
+}
+
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = get_subscriber(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e404("The subscriber does not exist."))?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut lists_html = String::new();
+    for membership in get_memberships(&pool, subscriber_id).await.map_err(e500)? {
+        writeln!(
+            lists_html,
+            "<li>{} ({})</li>",
+            htmlescape::encode_minimal(&membership.name),
+            membership.status
+        ).unwrap();
+    }
+    let mut tags_html = String::new();
+    for tag in get_tag_names(&pool, subscriber_id).await.map_err(e500)? {
+        writeln!(tags_html, "<li>{}</li>", htmlescape::encode_minimal(&tag)).unwrap();
+    }
+    let mut attributes_html = String::new();
+    if let serde_json::Value::Object(attributes) = &subscriber.attributes {
+        for (key, value) in attributes {
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string()
+            };
+            writeln!(
+                attributes_html,
+                "<li>{}: {}</li>",
+                htmlescape::encode_minimal(key),
+                htmlescape::encode_minimal(&value)
+            ).unwrap();
+        }
+    }
+
+    let mut consent_html = String::new();
+    for event in get_consent_events(&pool, subscriber_id).await.map_err(e500)? {
+        writeln!(
+            consent_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            event.occurred_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            event.event,
+            htmlescape::encode_minimal(&event.source),
+            htmlescape::encode_minimal(event.ip_address.as_deref().unwrap_or_default()),
+            htmlescape::encode_minimal(event.user_agent.as_deref().unwrap_or_default()),
+            htmlescape::encode_minimal(event.consent_text_version.as_deref().unwrap_or_default())
+        ).unwrap();
+    }
+
+    let mut status_history_html = String::new();
+    for change in get_status_history(&pool, subscriber_id).await.map_err(e500)? {
+        writeln!(
+            status_history_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            change.changed_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            change.from_status.map(SubscriptionStatus::as_str).unwrap_or_default(),
+            change.to_status,
+            htmlescape::encode_minimal(&change.reason)
+        ).unwrap();
+    }
+
+    let email = htmlescape::encode_minimal(&subscriber.email);
+    let name = htmlescape::encode_attribute(&subscriber.name);
+    let status = subscriber.status;
+    let subscribed_at = subscriber.subscribed_at.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let signup_source = htmlescape::encode_minimal(subscriber.signup_source.as_deref().unwrap_or("unknown"));
+    let actions = format!("/admin/subscribers/{}", subscriber.id);
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        format!(r#"<!DOCTYPE html><html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Subscriber {email}</title>
+        </head>
+        <body>
+        {msg_html}
+        <h1>{email}</h1>
+        <p>Status: {status}</p>
+        <p>Subscribed at: {subscribed_at}</p>
+        <p>Source: {signup_source}</p>
+        <p>Lists:</p>
+        <ul>
+            {lists_html}
+        </ul>
+        <p>Tags:</p>
+        <ul>
+            {tags_html}
+        </ul>
+        <p>Attributes:</p>
+        <ul>
+            {attributes_html}
+        </ul>
+        <p>Consent record:</p>
+        <table>
+            <thead>
+                <tr><th>At</th><th>Event</th><th>Source</th><th>IP address</th><th>User agent</th><th>Consent text version</th></tr>
+            </thead>
+            <tbody>
+                {consent_html}
+            </tbody>
+        </table>
+        <p>Status history:</p>
+        <table>
+            <thead>
+                <tr><th>At</th><th>From</th><th>To</th><th>Reason</th></tr>
+            </thead>
+            <tbody>
+                {status_history_html}
+            </tbody>
+        </table>
+        <form action="{actions}/name" method="post">
+            <label>Name
+                <input type="text" name="name" value="{name}">
+            </label>
+            <button type="submit">Save name</button>
+        </form>
+        <form action="{actions}/confirm" method="post">
+            <button type="submit">Confirm without email</button>
+        </form>
+        <form action="{actions}/resend-confirmation" method="post">
+            <button type="submit">Resend confirmation email</button>
+        </form>
+        <form action="{actions}/unsubscribe" method="post">
+            <button type="submit">Unsubscribe</button>
+        </form>
+        <p><a href="{actions}/data-export">Download all data (JSON)</a></p>
+        <form action="{actions}/data-export" method="post">
+            <button type="submit">Email the subscriber a link to their data</button>
+        </form>
+        <form action="{actions}/delete" method="post">
+            <button type="submit">Erase permanently</button>
+        </form>
+        <p><a href="/admin/subscribers">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#)
+    ))
+}
+
+#[tracing::instrument(name = "Get subscriber details", skip(pool))]
+pub async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid
+) -> Result<Option<SubscriberDetails>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT id, email, punycode_email, name, status AS "status: SubscriptionStatus", subscribed_at, signup_source, attributes
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a subscriber.")?;
+    Ok(subscriber)
+}
+
+struct Membership {
+    name: String,
+    status: SubscriptionStatus
+}
+
+#[tracing::instrument(name = "Get the lists of a subscriber", skip(pool))]
+async fn get_memberships(
+    pool: &PgPool,
+    subscriber_id: Uuid
+) -> Result<Vec<Membership>, anyhow::Error> {
+    let memberships = sqlx::query_as!(
+        Membership,
+        r#"
+        SELECT l.name, m.status AS "status: SubscriptionStatus"
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY l.name
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the lists of a subscriber.")?;
+    Ok(memberships)
+}
+
+#[tracing::instrument(name = "Get the tags of a subscriber", skip(pool))]
+async fn get_tag_names(
+    pool: &PgPool,
+    subscriber_id: Uuid
+) -> Result<Vec<String>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT t.name
+        FROM subscriber_tags st
+        JOIN tags t ON t.tag_id = st.tag_id
+        WHERE st.subscriber_id = $1
+        ORDER BY t.name
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the tags of a subscriber.")?;
+    Ok(rows.into_iter().map(|r| r.name).collect())
+}
@@ -0,0 +1,201 @@
+use crate::domain::SubscriptionStatus;
+use crate::utils::e400;
+use actix_web::{HttpResponse, web};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web_lab::respond::{Csv, NdJson};
+use anyhow::Context;
+use chrono::{DateTime, Duration, NaiveDate, SecondsFormat, Utc};
+use futures_util::{stream, Stream, StreamExt, TryStreamExt};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Rows fetched per query: memory use is bounded by a batch, not by the table size.
+const BATCH_SIZE: i64 = 500;
+
+#[derive(serde::Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Ndjson
+}
+
+#[derive(serde::Deserialize)]
+pub struct QueryParameters {
+    format: ExportFormat,
+    status: Option<String>,
+    /// First day to include, as `YYYY-MM-DD` (UTC).
+    subscribed_from: Option<String>,
+    /// Last day to include, as `YYYY-MM-DD` (UTC).
+    subscribed_until: Option<String>
+}
+
+#[derive(Clone)]
+struct ExportFilter {
+    status: Option<SubscriptionStatus>,
+    subscribed_from: Option<DateTime<Utc>>,
+    subscribed_before: Option<DateTime<Utc>>
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>,
+    attributes: serde_json::Value
+}
+
+#[derive(serde::Serialize)]
+struct ExportedSubscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: SubscriptionStatus,
+    /// RFC 3339, in UTC.
+    subscribed_at: String,
+    attributes: serde_json::Value
+}
+
+impl From<SubscriberRow> for ExportedSubscriber {
+    fn from(row: SubscriberRow) -> Self {
+        Self {
+            id: row.id,
+            email: row.email,
+            name: row.name,
+            status: row.status,
+            subscribed_at: row.subscribed_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            attributes: row.attributes
+        }
+    }
+}
+
+impl ExportedSubscriber {
+    const CSV_HEADER: [&'static str; 6] = ["id", "email", "name", "status", "subscribed_at", "attributes"];
+
+    /// Attributes vary between subscribers, so they go in a single JSON column.
+    fn into_csv_record(self) -> [String; 6] {
+        [
+            self.id.to_string(),
+            self.email,
+            self.name,
+            self.status.to_string(),
+            self.subscribed_at,
+            self.attributes.to_string()
+        ]
+    }
+}
+
+/// Streams subscribers in `subscribed_at` order, one keyset-paginated batch at a time.
+#[tracing::instrument(name = "Export subscribers", skip(parameters, pool))]
+pub async fn export_subscribers(
+    parameters: web::Query<QueryParameters>,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let QueryParameters { format, status, subscribed_from, subscribed_until } = parameters.into_inner();
+    let status = status
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<SubscriptionStatus>())
+        .transpose()
+        .map_err(e400)?;
+    let filter = ExportFilter {
+        status,
+        subscribed_from: parse_day(subscribed_from)?,
+        subscribed_before: parse_day(subscribed_until)?.map(|day| day + Duration::days(1))
+    };
+    let subscribers = subscriber_stream(pool.into_inner(), filter);
+
+    let mut response = HttpResponse::Ok();
+    let filename = match format {
+        ExportFormat::Csv => "subscribers.csv",
+        ExportFormat::Ndjson => "subscribers.ndjson"
+    };
+    response.insert_header(ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(filename.into())]
+    });
+    Ok(match format {
+        ExportFormat::Csv => {
+            let header = ExportedSubscriber::CSV_HEADER.map(String::from);
+            let records = stream::once(async { Ok(header) })
+                .chain(subscribers.map_ok(ExportedSubscriber::into_csv_record));
+            response
+                .content_type(Csv::mime())
+                .body(Csv::new(records).into_body_stream())
+        }
+        ExportFormat::Ndjson => {
+            response
+                .content_type(NdJson::mime())
+                .body(NdJson::new(subscribers).into_body_stream())
+        }
+    })
+}
+
+fn parse_day(day: Option<String>) -> Result<Option<DateTime<Utc>>, actix_web::Error> {
+    match day.filter(|d| !d.is_empty()) {
+        None => Ok(None),
+        Some(day) => {
+            let day = NaiveDate::parse_from_str(&day, "%Y-%m-%d")
+                .map_err(|_| e400(format!("{} is not a valid date (expected YYYY-MM-DD).", day)))?;
+            Ok(Some(DateTime::from_utc(day.and_hms(0, 0, 0), Utc)))
+        }
+    }
+}
+
+fn subscriber_stream(
+    pool: std::sync::Arc<PgPool>,
+    filter: ExportFilter
+) -> impl Stream<Item = Result<ExportedSubscriber, anyhow::Error>> {
+    // `None` once the last batch has been read.
+    let initial_state = Some(None::<(DateTime<Utc>, Uuid)>);
+    stream::try_unfold(initial_state, move |state| {
+        let pool = pool.clone();
+        let filter = filter.clone();
+        async move {
+            let after = match state {
+                Some(after) => after,
+                None => return Ok(None)
+            };
+            let batch = get_batch(&pool, &filter, after).await?;
+            let next_state = if (batch.len() as i64) < BATCH_SIZE {
+                None
+            } else {
+                batch.last().map(|s| Some((s.subscribed_at, s.id)))
+            };
+            let batch = batch.into_iter().map(|row| Ok(ExportedSubscriber::from(row)));
+            Ok::<_, anyhow::Error>(Some((stream::iter(batch), next_state)))
+        }
+    })
+    .try_flatten()
+}
+
+#[tracing::instrument(name = "Get a batch of subscribers to export", skip(pool, filter))]
+async fn get_batch(
+    pool: &PgPool,
+    filter: &ExportFilter,
+    after: Option<(DateTime<Utc>, Uuid)>
+) -> Result<Vec<SubscriberRow>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+        SELECT id, email, name, status AS "status: SubscriptionStatus", subscribed_at, attributes
+        FROM subscriptions
+        WHERE
+            ($1::subscription_status IS NULL OR status = $1) AND
+            ($2::timestamptz IS NULL OR subscribed_at >= $2) AND
+            ($3::timestamptz IS NULL OR subscribed_at < $3) AND
+            ($4::timestamptz IS NULL OR (subscribed_at, id) > ($4, $5::uuid))
+        ORDER BY subscribed_at, id
+        LIMIT $6
+        "#,
+        filter.status as Option<SubscriptionStatus>,
+        filter.subscribed_from,
+        filter.subscribed_before,
+        after.map(|(subscribed_at, _)| subscribed_at),
+        after.map(|(_, id)| id),
+        BATCH_SIZE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve a batch of subscribers to export.")?;
+    Ok(subscribers)
+}
@@ -0,0 +1,218 @@
+use crate::domain::SubscriptionStatus;
+use crate::utils::{e400, e500};
+use std::fmt::Write;
+use actix_web::{HttpResponse, web, http::header::ContentType};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParameters {
+    /// Prefix of the email address or of the name, case-insensitive.
+    q: Option<String>,
+    status: Option<String>,
+    /// Where the previous page ended, as produced by `Cursor::encode`.
+    after: Option<String>
+}
+
+/// Position in the `(subscribed_at, id)` ordering, so that a page is
+/// an index range scan however deep into the table it is.
+struct Cursor {
+    subscribed_at: DateTime<Utc>,
+    id: Uuid
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        format!(
+            "{}_{}",
+            self.subscribed_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.id
+        )
+    }
+
+    fn decode(s: &str) -> Result<Cursor, anyhow::Error> {
+        let (subscribed_at, id) = s.split_once('_').context("The cursor is malformed.")?;
+        Ok(Self {
+            subscribed_at: DateTime::parse_from_rfc3339(subscribed_at)
+                .context("The cursor is malformed.")?
+                .with_timezone(&Utc),
+            id: Uuid::parse_str(id).context("The cursor is malformed.")?
+        })
+    }
+}
+
+struct SubscriberSummary {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>
+}
+
+pub async fn list_subscribers(
+    parameters: web::Query<QueryParameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let QueryParameters { q, status, after } = parameters.into_inner();
+    let q = q.filter(|q| !q.trim().is_empty());
+    let status = status
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<SubscriptionStatus>())
+        .transpose()
+        .map_err(e400)?;
+    let after = after.as_deref().map(Cursor::decode).transpose().map_err(e400)?;
+
+    let mut subscribers = get_subscribers_page(&pool, q.as_deref(), status, after.as_ref())
+        .await
+        .map_err(e500)?;
+    let next_page = if subscribers.len() as i64 > PAGE_SIZE {
+        subscribers.truncate(PAGE_SIZE as usize);
+        subscribers.last().map(|s| Cursor { subscribed_at: s.subscribed_at, id: s.id })
+    } else {
+        None
+    };
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for subscriber in &subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr><td><a href="/admin/subscribers/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            subscriber.id,
+            htmlescape::encode_minimal(&subscriber.email),
+            htmlescape::encode_minimal(&subscriber.name),
+            subscriber.status,
+            subscriber.subscribed_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ).unwrap();
+    }
+
+    let filters_query = serde_html_form::to_string([
+        ("q", q.as_deref().unwrap_or_default()),
+        ("status", status.map(SubscriptionStatus::as_str).unwrap_or_default())
+    ]).map_err(e500)?;
+    let mut pagination_html = String::new();
+    if after.is_some() {
+        writeln!(
+            pagination_html,
+            r#"<a href="/admin/subscribers?{}">First page</a>"#,
+            htmlescape::encode_minimal(&filters_query)
+        ).unwrap();
+    }
+    if let Some(next_page) = next_page {
+        writeln!(
+            pagination_html,
+            r#"<a href="/admin/subscribers?{}&amp;after={}">Next page</a>"#,
+            htmlescape::encode_minimal(&filters_query),
+            urlencoding::encode(&next_page.encode())
+        ).unwrap();
+    }
+
+    let mut status_options_html = String::new();
+    for s in SubscriptionStatus::ALL {
+        let selected = if status == Some(s) { " selected" } else { "" };
+        writeln!(status_options_html, r#"<option value="{s}"{selected}>{s}</option>"#).unwrap();
+    }
+    let q = htmlescape::encode_attribute(q.as_deref().unwrap_or_default());
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        format!(r#"<!DOCTYPE html><html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Subscribers</title>
+        </head>
+        <body>
+        {msg_html}
+        <form action="/admin/subscribers" method="get">
+            <label>Email or name starts with
+                <input type="search" name="q" value="{q}">
+            </label>
+            <label>Status
+                <select name="status">
+                    <option value="">any</option>
+                    {status_options_html}
+                </select>
+            </label>
+            <button type="submit">Search</button>
+        </form>
+        <table>
+            <thead>
+                <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th></tr>
+            </thead>
+            <tbody>
+                {rows_html}
+            </tbody>
+        </table>
+        <p>{pagination_html}</p>
+        <form action="/admin/subscribers/export" method="get">
+            <label>Status
+                <select name="status">
+                    <option value="">any</option>
+                    {status_options_html}
+                </select>
+            </label>
+            <label>Subscribed from
+                <input type="date" name="subscribed_from">
+            </label>
+            <label>until
+                <input type="date" name="subscribed_until">
+            </label>
+            <select name="format">
+                <option value="csv">CSV</option>
+                <option value="ndjson">NDJSON</option>
+            </select>
+            <button type="submit">Export</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#)
+    ))
+}
+
+/// Fetches one row more than a page, to tell whether there is a next page.
+#[tracing::instrument(name = "Get a page of subscribers", skip(pool, after))]
+async fn get_subscribers_page(
+    pool: &PgPool,
+    q: Option<&str>,
+    status: Option<SubscriptionStatus>,
+    after: Option<&Cursor>
+) -> Result<Vec<SubscriberSummary>, anyhow::Error> {
+    let prefix_pattern = q.map(|q| format!("{}%", escape_like(&q.trim().to_lowercase())));
+    let subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, status AS "status: SubscriptionStatus", subscribed_at
+        FROM subscriptions
+        WHERE
+            ($1::text IS NULL OR lower(email) LIKE $1 OR lower(name) LIKE $1) AND
+            ($2::subscription_status IS NULL OR status = $2) AND
+            ($3::timestamptz IS NULL OR (subscribed_at, id) < ($3, $4::uuid))
+        ORDER BY subscribed_at DESC, id DESC
+        LIMIT $5
+        "#,
+        prefix_pattern,
+        status as Option<SubscriptionStatus>,
+        after.map(|c| c.subscribed_at),
+        after.map(|c| c.id),
+        PAGE_SIZE + 1
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve a page of subscribers.")?;
+    Ok(subscribers)
+}
+
+/// Makes user input match literally in a `LIKE` pattern.
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
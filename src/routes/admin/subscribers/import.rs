@@ -0,0 +1,356 @@
+use crate::confirmation_email::enqueue_confirmation_email;
+use crate::domain::{EmailPolicy, ErasureTombstone, NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::routes::admin::lists::get_lists;
+use crate::routes::consent::{record_consent_event, ConsentContext, ConsentEventKind};
+use crate::routes::subscription_status::record_initial_status;
+use crate::startup::HmacSecret;
+use crate::utils::{e400, e500};
+use std::collections::HashMap;
+use std::fmt::Write;
+use actix_web::{HttpRequest, HttpResponse, web, http::header::ContentType};
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Consent was collected elsewhere: subscribers are confirmed straight away.
+    Confirmed,
+    /// Subscribers are added as pending and receive a confirmation email,
+    /// sent by the background worker.
+    DoubleOptIn
+}
+
+impl ImportMode {
+    /// Consent recorded for each imported subscriber.
+    fn consent_event(self) -> ConsentEventKind {
+        match self {
+            Self::Confirmed => ConsentEventKind::Confirmed,
+            Self::DoubleOptIn => ConsentEventKind::Subscribed
+        }
+    }
+
+    fn status(self) -> SubscriptionStatus {
+        match self {
+            Self::Confirmed => SubscriptionStatus::Confirmed,
+            Self::DoubleOptIn => SubscriptionStatus::PendingConfirmation
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    csv: String,
+    mode: ImportMode,
+    list_id: Uuid
+}
+
+struct ImportRow {
+    line: u64,
+    subscriber: NewSubscriber,
+    canonical_email: String
+}
+
+#[derive(Default)]
+struct ImportReport {
+    imported: usize,
+    /// Rows left untouched on purpose: repeated addresses and existing subscribers.
+    skipped: Vec<String>,
+    errors: Vec<String>
+}
+
+pub async fn import_subscribers_form(
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = import_page(&pool, "").await?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(body))
+}
+
+/// Rows are processed in file order. The first row for an address wins,
+/// and addresses that are already known (whatever their status) are never modified,
+/// so that an import cannot re-subscribe someone who opted out.
+/// Addresses whose owner asked to be erased are skipped for the same reason.
+#[tracing::instrument(
+    name = "Import subscribers from CSV",
+    skip(body, request, pool, email_policy, hmac_secret)
+)]
+pub async fn import_subscribers(
+    body: web::Bytes,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    email_policy: web::Data<EmailPolicy>,
+    hmac_secret: web::Data<HmacSecret>
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { csv, mode, list_id } = serde_html_form::from_bytes(&body).map_err(e400)?;
+    if !get_lists(&pool).await.map_err(e500)?.iter().any(|l| l.list_id == list_id) {
+        return Err(e400("The import targets a list that does not exist."));
+    }
+
+    let consent = ConsentContext::from_request(&request, "import", None);
+    let mut report = ImportReport::default();
+    let rows = match parse_csv(&csv, &email_policy, &mut report) {
+        Ok(rows) => rows,
+        Err(e) => {
+            let report_html = format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&e));
+            let body = import_page(&pool, &report_html).await?;
+            return Ok(HttpResponse::Ok().content_type(ContentType::html()).body(body));
+        }
+    };
+
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    for row in rows {
+        let is_erased = has_tombstone(&mut transaction, &row, &hmac_secret.0)
+            .await
+            .context("Failed to look up erasure tombstones.")
+            .map_err(e500)?;
+        if is_erased {
+            report.skipped.push(format!(
+                "Line {}: {} was erased at the owner's request and cannot be imported.",
+                row.line,
+                row.subscriber.email.as_ref()
+            ));
+            continue;
+        }
+        let subscriber_id = insert_imported_subscriber(&mut transaction, &row, list_id, mode)
+            .await
+            .context("Failed to store an imported subscriber.")
+            .map_err(e500)?;
+        let subscriber_id = match subscriber_id {
+            Some(subscriber_id) => subscriber_id,
+            None => {
+                report.skipped.push(format!(
+                    "Line {}: {} is already a subscriber.",
+                    row.line,
+                    row.subscriber.email.as_ref()
+                ));
+                continue;
+            }
+        };
+        report.imported += 1;
+        record_consent_event(&mut transaction, subscriber_id, mode.consent_event(), &consent)
+            .await
+            .context("Failed to record the consent of an imported subscriber.")
+            .map_err(e500)?;
+        if let ImportMode::DoubleOptIn = mode {
+            enqueue_confirmation_email(&mut transaction, subscriber_id)
+                .await
+                .context("Failed to queue the confirmation email of an imported subscriber.")
+                .map_err(e500)?;
+        }
+    }
+    transaction.commit()
+        .await
+        .context("Failed to commit SQL transaction to import subscribers.")
+        .map_err(e500)?;
+
+    let body = import_page(&pool, &report_html(&report, mode)).await?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(body))
+}
+
+/// Expects a header row with `email` and `name` columns. Any other column is
+/// stored as an attribute named after its header; empty cells are left out.
+fn parse_csv(csv: &str, email_policy: &EmailPolicy, report: &mut ImportReport) -> Result<Vec<ImportRow>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(csv.as_bytes());
+    let headers: Vec<String> = reader.headers()
+        .map_err(|e| format!("The CSV header could not be read: {}", e))?
+        .iter()
+        .map(|h| h.to_lowercase())
+        .collect();
+    let column = |name: &str| headers
+        .iter()
+        .position(|h| h == name)
+        .ok_or_else(|| format!("The CSV header must contain an `{}` column.", name));
+    let email_column = column("email")?;
+    let name_column = column("name")?;
+
+    let mut rows = Vec::new();
+    // Keyed by canonical address, so that aliases of one mailbox count as repeats.
+    let mut first_line_by_email = HashMap::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or_default();
+                report.errors.push(format!("Line {}: {}", line, e));
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let attributes = headers
+            .iter()
+            .zip(record.iter())
+            .enumerate()
+            .filter(|(i, (_, value))| *i != email_column && *i != name_column && !value.is_empty())
+            .map(|(_, (header, value))| (header.clone(), value.to_string()))
+            .collect();
+        let subscriber = SubscriberEmail::parse(record[email_column].to_string()).and_then(|email| {
+            Ok(NewSubscriber {
+                email,
+                name: SubscriberName::parse(record[name_column].to_string())?,
+                attributes: SubscriberAttributes::parse(attributes)?
+            })
+        });
+        match subscriber {
+            Ok(subscriber) => {
+                let canonical_email = email_policy.canonical(&subscriber.email);
+                if let Some(first_line) = first_line_by_email.get(&canonical_email) {
+                    report.skipped.push(format!(
+                        "Line {}: {} already appears on line {}.",
+                        line,
+                        subscriber.email.as_ref(),
+                        first_line
+                    ));
+                    continue;
+                }
+                first_line_by_email.insert(canonical_email.clone(), line);
+                rows.push(ImportRow { line, subscriber, canonical_email });
+            }
+            Err(e) => report.errors.push(format!("Line {}: {}", line, e))
+        }
+    }
+    Ok(rows)
+}
+
+/// Tombstones are keyed on the canonical address; those left before that was the
+/// case are keyed on the address itself.
+#[tracing::instrument(skip(transaction, row, hmac_secret))]
+async fn has_tombstone(
+    transaction: &mut Transaction<'_, Postgres>,
+    row: &ImportRow,
+    hmac_secret: &Secret<String>
+) -> Result<bool, sqlx::Error> {
+    let tombstone = ErasureTombstone::compute(&row.canonical_email, hmac_secret);
+    let legacy_tombstone = ErasureTombstone::compute(row.subscriber.email.as_ref(), hmac_secret);
+    let row = sqlx::query!(
+        r#"SELECT email_hash FROM erasure_tombstones WHERE email_hash = $1 OR email_hash = $2 LIMIT 1"#,
+        tombstone.as_ref(),
+        legacy_tombstone.as_ref()
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(row.is_some())
+}
+
+/// Returns `None` if a subscriber with the same email address already exists.
+#[tracing::instrument(skip(transaction, row))]
+async fn insert_imported_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    row: &ImportRow,
+    list_id: Uuid,
+    mode: ImportMode
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let subscriber_id = Uuid::new_v4();
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (
+            id, email, punycode_email, name, subscribed_at, status, attributes, signup_source, canonical_email
+        )
+        VALUES ($1, $2, $3, $4, now(), $5, $6, 'import', $7)
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        row.subscriber.email.as_ref(),
+        row.subscriber.email.punycode(),
+        row.subscriber.name.as_ref(),
+        mode.status() as SubscriptionStatus,
+        row.subscriber.attributes.to_json(),
+        row.canonical_email
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if n_inserted_rows == 0 {
+        return Ok(None);
+    }
+    record_initial_status(transaction, subscriber_id, mode.status(), "import").await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status)
+        VALUES ($1, $2, $3)
+        "#,
+        list_id,
+        subscriber_id,
+        mode.status() as SubscriptionStatus
+    )
+    .execute(transaction)
+    .await?;
+    Ok(Some(subscriber_id))
+}
+
+fn report_html(report: &ImportReport, mode: ImportMode) -> String {
+    let mut html = String::new();
+    writeln!(
+        html,
+        "<p><i>Imported {} subscribers, skipped {} rows, {} rows had errors.</i></p>",
+        report.imported,
+        report.skipped.len(),
+        report.errors.len()
+    ).unwrap();
+    if let ImportMode::DoubleOptIn = mode {
+        writeln!(html, "<p><i>Confirmation emails will go out shortly.</i></p>").unwrap();
+    }
+    for (title, messages) in [("Errors", &report.errors), ("Skipped", &report.skipped)] {
+        if messages.is_empty() {
+            continue;
+        }
+        writeln!(html, "<h2>{}</h2><ul>", title).unwrap();
+        for message in messages {
+            writeln!(html, "<li>{}</li>", htmlescape::encode_minimal(message)).unwrap();
+        }
+        writeln!(html, "</ul>").unwrap();
+    }
+    html
+}
+
+async fn import_page(pool: &PgPool, report_html: &str) -> Result<String, actix_web::Error> {
+    let mut lists_html = String::new();
+    for list in get_lists(pool).await.map_err(e500)? {
+        let selected = if list.is_default { " selected" } else { "" };
+        writeln!(
+            lists_html,
+            r#"<option value="{}"{}>{}</option>"#,
+            list.list_id,
+            selected,
+            htmlescape::encode_minimal(&list.name)
+        ).unwrap();
+    }
+
+    Ok(format!(r#"<!DOCTYPE html><html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>Import subscribers</title>
+    </head>
+    <body>
+    {report_html}
+    <form action="/admin/subscribers/import" method="post">
+        <label>CSV with an <code>email</code> and a <code>name</code> column.
+            Other columns are stored as attributes.
+            <textarea placeholder="email,name,company" name="csv" rows="20" cols="80"></textarea>
+        </label>
+        <br>
+        <label>List
+            <select name="list_id">
+                {lists_html}
+            </select>
+        </label>
+        <br>
+        <label><input type="radio" name="mode" value="double_opt_in" checked>
+            Send a confirmation email (double opt-in)</label>
+        <br>
+        <label><input type="radio" name="mode" value="confirmed">
+            Import as confirmed (consent already held)</label>
+        <br>
+        <button type="submit">Import</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+    </body>
+    </html>
+    "#))
+}
@@ -0,0 +1,12 @@
+mod actions;
+mod data_export;
+mod detail;
+mod export;
+mod get;
+mod import;
+pub use actions::{admin_resend_confirmation, admin_unsubscribe_subscriber, delete_subscriber, force_confirm_subscriber, rename_subscriber};
+pub use data_export::{download_subscriber_data, send_subscriber_data_export};
+pub use detail::subscriber_details;
+pub use export::export_subscribers;
+pub use get::list_subscribers;
+pub use import::{import_subscribers, import_subscribers_form};
\ No newline at end of file
@@ -0,0 +1,86 @@
+use crate::utils::e500;
+use std::fmt::Write;
+use actix_web::{HttpResponse, web, http::header::ContentType};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub struct Tag {
+    pub tag_id: Uuid,
+    pub name: String,
+    pub n_subscribers: i64
+}
+
+pub async fn tags_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut tags_html = String::new();
+    for tag in get_tags(&pool).await.map_err(e500)? {
+        writeln!(
+            tags_html,
+            "<li>{} ({} subscribers)</li>",
+            htmlescape::encode_minimal(&tag.name),
+            tag.n_subscribers
+        ).unwrap();
+    }
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        format!(r#"<!DOCTYPE html><html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Subscriber tags</title>
+        </head>
+        <body>
+        {msg_html}
+        <ul>
+            {tags_html}
+        </ul>
+        <form action="/admin/tags" method="post">
+            <label>Email
+                <input type="email" placeholder="Enter the subscriber email" name="email">
+            </label>
+            <label>Tag
+                <input type="text" placeholder="e.g. beta" name="tag">
+            </label>
+            <button type="submit">Add tag</button>
+        </form>
+        <form action="/admin/tags/remove" method="post">
+            <label>Email
+                <input type="email" placeholder="Enter the subscriber email" name="email">
+            </label>
+            <label>Tag
+                <input type="text" placeholder="e.g. beta" name="tag">
+            </label>
+            <button type="submit">Remove tag</button>
+        </form>
+        <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#)
+    ))
+}
+
+#[tracing::instrument(name = "Get subscriber tags", skip(pool))]
+pub async fn get_tags(pool: &PgPool) -> Result<Vec<Tag>, anyhow::Error> {
+    let tags = sqlx::query_as!(
+        Tag,
+        r#"
+        SELECT t.tag_id, t.name, COUNT(st.subscriber_id) as "n_subscribers!"
+        FROM tags t
+        LEFT JOIN subscriber_tags st ON st.tag_id = t.tag_id
+        GROUP BY t.tag_id, t.name
+        ORDER BY t.name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscriber tags.")?;
+    Ok(tags)
+}
@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::{get_tags, tags_form, Tag};
+pub use post::{tag_subscriber, untag_subscriber};
\ No newline at end of file
@@ -0,0 +1,131 @@
+use crate::utils::{e500, see_other};
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    tag: String
+}
+
+#[tracing::instrument(name = "Tag a subscriber", skip(form, pool))]
+pub async fn tag_subscriber(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let tag = form.0.tag.trim();
+    if tag.is_empty() {
+        FlashMessage::error("The tag cannot be empty.").send();
+        return Ok(see_other("/admin/tags"));
+    }
+
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let subscriber_id = match get_subscriber_id(&mut transaction, form.0.email.trim())
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            FlashMessage::error("There is no subscriber with this email address.").send();
+            return Ok(see_other("/admin/tags"));
+        }
+    };
+    let tag_id = get_or_create_tag(&mut transaction, tag)
+        .await
+        .map_err(e500)?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        tag_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to tag a subscriber.")
+    .map_err(e500)?;
+    transaction.commit()
+        .await
+        .context("Failed to commit SQL transaction to tag a subscriber.")
+        .map_err(e500)?;
+
+    FlashMessage::info("The subscriber has been tagged.").send();
+    Ok(see_other("/admin/tags"))
+}
+
+#[tracing::instrument(name = "Remove a tag from a subscriber", skip(form, pool))]
+pub async fn untag_subscriber(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM subscriber_tags st
+        USING subscriptions s, tags t
+        WHERE
+            st.subscriber_id = s.id AND
+            st.tag_id = t.tag_id AND
+            s.email = $1 AND
+            t.name = $2
+        "#,
+        form.0.email.trim(),
+        form.0.tag.trim()
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to remove a tag from a subscriber.")
+    .map_err(e500)?;
+
+    FlashMessage::info("The tag has been removed.").send();
+    Ok(see_other("/admin/tags"))
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn get_subscriber_id(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email = $1"#,
+        email
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to look up a subscriber by email.")?;
+    Ok(row.map(|r| r.id))
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn get_or_create_tag(
+    transaction: &mut Transaction<'_, Postgres>,
+    name: &str
+) -> Result<Uuid, anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO tags (tag_id, name)
+        VALUES ($1, $2)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store a new tag.")?;
+    let row = sqlx::query!(
+        r#"SELECT tag_id FROM tags WHERE name = $1"#,
+        name
+    )
+    .fetch_one(transaction)
+    .await
+    .context("Failed to retrieve a tag.")?;
+    Ok(row.tag_id)
+}
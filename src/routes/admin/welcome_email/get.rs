@@ -0,0 +1,59 @@
+use crate::utils::e500;
+use crate::welcome_email::get_welcome_email;
+use std::fmt::Write;
+use actix_web::{HttpResponse, web, http::header::ContentType};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+pub async fn welcome_email_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let welcome_email = get_welcome_email(&pool).await.map_err(e500)?;
+    let checked = if welcome_email.enabled { " checked" } else { "" };
+    let subject = htmlescape::encode_minimal(&welcome_email.subject);
+    let html_content = htmlescape::encode_minimal(&welcome_email.html_content);
+    let text_content = htmlescape::encode_minimal(&welcome_email.text_content);
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        format!(r#"<!DOCTYPE html><html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Welcome email</title>
+        </head>
+        <body>
+            {msg_html}
+            <p>Sent to every subscriber once they confirm their subscription.</p>
+            <form action="/admin/welcome-email" method="post">
+                <label>
+                    <input type="checkbox" name="enabled"{checked}>
+                    Send the welcome email
+                </label>
+                <br>
+                <label>Subject
+                    <input type="text" name="subject" value="{subject}">
+                </label>
+                <br>
+                <p>Personalise the content with <code>{{{{ name }}}}</code>, <code>{{{{ email }}}}</code>
+                    or <code>{{{{ attributes.company | default("your team") }}}}</code>.</p>
+                <label>HTML Content
+                    <textarea name="html_content">{html_content}</textarea>
+                </label>
+                <br>
+                <label>Plain Text Content
+                    <textarea name="text_content">{text_content}</textarea>
+                </label>
+                <br>
+                <button type="submit">Save</button>
+            </form>
+            <p><a href="/admin/dashboard">&lt;- Back</a></p>
+        </body>
+        </html>
+        "#)
+    ))
+}
@@ -0,0 +1,4 @@
+mod get;
+mod post;
+pub use get::welcome_email_form;
+pub use post::save_welcome_email;
\ No newline at end of file
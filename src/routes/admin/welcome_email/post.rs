@@ -0,0 +1,56 @@
+use crate::domain::IssueTemplate;
+use crate::utils::{e500, see_other};
+use actix_web::{HttpResponse, web};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    /// An unchecked checkbox is not sent at all.
+    enabled: Option<String>,
+    subject: String,
+    html_content: String,
+    text_content: String
+}
+
+#[tracing::instrument(name = "Save the welcome email", skip(form, pool))]
+pub async fn save_welcome_email(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { enabled, subject, html_content, text_content } = form.into_inner();
+    let subject = subject.trim();
+    if subject.is_empty() {
+        FlashMessage::error("The subject cannot be empty.").send();
+        return Ok(see_other("/admin/welcome-email"));
+    }
+    // Templates are expanded by the delivery worker: reject broken ones before any is queued.
+    for (content, label) in [(&html_content, "HTML content"), (&text_content, "plain text content")] {
+        if let Err(e) = IssueTemplate::parse(content) {
+            FlashMessage::error(format!(
+                "The {} is not a valid template: {}",
+                label,
+                htmlescape::encode_minimal(&e)
+            )).send();
+            return Ok(see_other("/admin/welcome-email"));
+        }
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE welcome_email
+        SET enabled = $1, subject = $2, html_content = $3, text_content = $4, updated_at = now()
+        "#,
+        enabled.is_some(),
+        subject,
+        html_content,
+        text_content
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to save the welcome email.")
+    .map_err(e500)?;
+    FlashMessage::info("The welcome email has been saved.").send();
+    Ok(see_other("/admin/welcome-email"))
+}
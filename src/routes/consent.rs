@@ -0,0 +1,110 @@
+use crate::client_ip::client_ip;
+use actix_web::HttpRequest;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Longest user agent we keep; anything past it is noise, not evidence.
+const MAX_USER_AGENT_LENGTH: usize = 512;
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum ConsentEventKind {
+    /// Consent was given and a confirmation email is on its way.
+    Subscribed,
+    /// The address was confirmed, or consent was vouched for by an admin.
+    Confirmed
+}
+
+impl ConsentEventKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Subscribed => "subscribed",
+            Self::Confirmed => "confirmed"
+        }
+    }
+}
+
+/// Where a consent event comes from, taken from the request that caused it.
+#[derive(Debug)]
+pub(crate) struct ConsentContext {
+    pub source: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub consent_text_version: Option<String>
+}
+
+impl ConsentContext {
+    pub(crate) fn from_request(
+        request: &HttpRequest,
+        source: impl Into<String>,
+        consent_text_version: Option<String>
+    ) -> Self {
+        let user_agent = request
+            .headers()
+            .get("User-Agent")
+            .and_then(|h| h.to_str().ok())
+            .map(|ua| ua.chars().take(MAX_USER_AGENT_LENGTH).collect());
+        Self {
+            source: source.into(),
+            ip_address: client_ip(request).map(|ip| ip.to_string()),
+            user_agent,
+            consent_text_version
+        }
+    }
+}
+
+pub(crate) struct ConsentEvent {
+    pub event: String,
+    pub source: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub consent_text_version: Option<String>,
+    pub occurred_at: DateTime<Utc>
+}
+
+#[tracing::instrument(name = "Record a consent event", skip(transaction, context))]
+pub(crate) async fn record_consent_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    kind: ConsentEventKind,
+    context: &ConsentContext
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_events (
+            consent_event_id, subscriber_id, event, source, ip_address, user_agent, consent_text_version
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        kind.as_str(),
+        context.source,
+        context.ip_address,
+        context.user_agent,
+        context.consent_text_version
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Oldest first.
+#[tracing::instrument(name = "Get the consent events of a subscriber", skip(pool))]
+pub(crate) async fn get_consent_events(
+    pool: &PgPool,
+    subscriber_id: Uuid
+) -> Result<Vec<ConsentEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentEvent,
+        r#"
+        SELECT event, source, ip_address, user_agent, consent_text_version, occurred_at
+        FROM consent_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at, consent_event_id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -1,12 +1,22 @@
+mod consent;
 mod health_check;
+mod subscription_status;
 mod subscriptions;
+mod subscriptions_challenge;
 mod subscriptions_confirm;
+mod subscriptions_data_export;
+mod subscriptions_unsubscribe;
 mod home;
 mod login;
 mod admin;
+mod widget;
 pub use health_check::*;
 pub use subscriptions::*;
+pub use subscriptions_challenge::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_data_export::*;
+pub use subscriptions_unsubscribe::*;
 pub use home::*;
 pub use login::*;
 pub use admin::*;
+pub use widget::*;
@@ -0,0 +1,120 @@
+use crate::domain::{InvalidStatusTransition, SubscriptionStatus};
+use crate::routes::error_chain_fmt;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(thiserror::Error)]
+pub(crate) enum StatusTransitionError {
+    #[error("There is no subscriber with id {0}.")]
+    UnknownSubscriber(Uuid),
+    #[error(transparent)]
+    Invalid(#[from] InvalidStatusTransition),
+    #[error("Failed to change the status of a subscriber.")]
+    Database(#[from] sqlx::Error)
+}
+impl std::fmt::Debug for StatusTransitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+pub(crate) struct StatusChange {
+    pub from_status: Option<SubscriptionStatus>,
+    pub to_status: SubscriptionStatus,
+    pub reason: String,
+    pub changed_at: DateTime<Utc>
+}
+
+/// Starts the history of a subscriber that was just inserted with `status`.
+#[tracing::instrument(name = "Record the initial status of a subscriber", skip(transaction))]
+pub(crate) async fn record_initial_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    status: SubscriptionStatus,
+    reason: &str
+) -> Result<(), sqlx::Error> {
+    record_status_change(transaction, subscriber_id, None, status, reason).await
+}
+
+/// Moves a subscriber to `to` if the domain allows it, and records the change.
+/// Asking for the status the subscriber already has changes nothing.
+/// Returns the status the subscriber had.
+#[tracing::instrument(name = "Change the status of a subscriber", skip(transaction))]
+pub(crate) async fn transition_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    to: SubscriptionStatus,
+    reason: &str
+) -> Result<SubscriptionStatus, StatusTransitionError> {
+    let from = sqlx::query!(
+        r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .ok_or(StatusTransitionError::UnknownSubscriber(subscriber_id))?
+    .status;
+    if from == to {
+        return Ok(from);
+    }
+    from.transition_to(to)?;
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE id = $1"#,
+        subscriber_id,
+        to as SubscriptionStatus
+    )
+    .execute(&mut *transaction)
+    .await?;
+    record_status_change(transaction, subscriber_id, Some(from), to, reason).await?;
+    Ok(from)
+}
+
+async fn record_status_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    from: Option<SubscriptionStatus>,
+    to: SubscriptionStatus,
+    reason: &str
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_status_history (
+            subscription_status_change_id, subscriber_id, from_status, to_status, reason
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        from as Option<SubscriptionStatus>,
+        to as SubscriptionStatus,
+        reason
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Oldest first.
+#[tracing::instrument(name = "Get the status history of a subscriber", skip(pool))]
+pub(crate) async fn get_status_history(
+    pool: &PgPool,
+    subscriber_id: Uuid
+) -> Result<Vec<StatusChange>, sqlx::Error> {
+    sqlx::query_as!(
+        StatusChange,
+        r#"
+        SELECT
+            from_status AS "from_status: SubscriptionStatus",
+            to_status AS "to_status: SubscriptionStatus",
+            reason,
+            changed_at
+        FROM subscription_status_history
+        WHERE subscriber_id = $1
+        ORDER BY changed_at, subscription_status_change_id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+}
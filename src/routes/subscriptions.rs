@@ -1,30 +1,226 @@
+use std::collections::BTreeMap;
 use std::fmt::Formatter;
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::client_ip::client_ip;
+use crate::configuration::BotProtectionSettings;
+use crate::domain::{EmailPolicy, NewSubscriber, SubscribeChallenge, SubscribeChallengeError, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus};
 use crate::email_client::EmailClient;
-use crate::startup::ApplicationBaseUrl;
-use actix_web::{web, HttpResponse, ResponseError};
+use crate::mx_check::MxChecker;
+use crate::rate_limit::{RateLimiter, RetryAfter};
+use crate::routes::consent::{record_consent_event, ConsentContext, ConsentEventKind};
+use crate::routes::subscription_status::{record_initial_status, transition_status, StatusTransitionError};
+use crate::routes::widget::EmbeddingSite;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, ResponseError};
 use actix_web::web::Data;
-use actix_web::http::StatusCode;
+use actix_web::http::{header, StatusCode};
 use anyhow::Context;
 use chrono::Utc;
 use rand::{Rng, thread_rng};
 use rand::distributions::Alphanumeric;
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
 use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{field::display, Span};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    #[serde(default, rename = "list_id")]
+    list_ids: Vec<Uuid>,
+    /// Which form the subscriber used, for the consent record.
+    source: Option<String>,
+    /// Version of the consent wording the form displayed.
+    consent_text_version: Option<String>,
+    /// Honeypot: hidden from people, so only a script fills it in.
+    website: Option<String>,
+    /// From `/subscriptions/challenge`, with the proof-of-work `nonce` when one is asked for.
+    challenge: Option<String>,
+    nonce: Option<String>,
+    /// Filled from `attributes.<key>` fields, which have no fixed names.
+    #[serde(skip)]
+    attributes: Vec<(String, String)>,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+/// Body of a JSON subscription request. Missing names and emails are reported
+/// per field, like any other invalid value, rather than as a malformed body.
+#[derive(serde::Deserialize)]
+pub struct JsonData {
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    list_ids: Vec<Uuid>,
+    #[serde(default)]
+    attributes: BTreeMap<String, String>,
+    source: Option<String>,
+    consent_text_version: Option<String>,
+    website: Option<String>,
+    challenge: Option<String>,
+    nonce: Option<String>
+}
+
+impl From<JsonData> for FormData {
+    fn from(value: JsonData) -> Self {
+        Self {
+            email: value.email,
+            name: value.name,
+            list_ids: value.list_ids,
+            source: value.source,
+            consent_text_version: value.consent_text_version,
+            website: value.website,
+            challenge: value.challenge,
+            nonce: value.nonce,
+            attributes: value.attributes.into_iter().collect()
+        }
+    }
+}
 
+struct Subscription {
+    subscriber: NewSubscriber,
+    list_ids: Vec<Uuid>,
+    source: String,
+    consent_text_version: Option<String>
+}
+
+impl TryFrom<FormData> for Subscription {
+    type Error = FieldErrors;
+
+    /// Every field is checked, so that all the mistakes can be reported at once.
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { email, name })
+        let mut errors = FieldErrors::default();
+        let name = errors.check("name", SubscriberName::parse(value.name));
+        let email = errors.check("email", SubscriberEmail::parse(value.email));
+        let attributes = errors.check("attributes", SubscriberAttributes::parse(value.attributes));
+        let source = errors.check("source", parse_consent_label(value.source, "source"));
+        let consent_text_version = errors.check(
+            "consent_text_version",
+            parse_consent_label(value.consent_text_version, "consent text version")
+        );
+        match (name, email, attributes, source, consent_text_version) {
+            (Some(name), Some(email), Some(attributes), Some(source), Some(consent_text_version)) => Ok(Self {
+                subscriber: NewSubscriber { email, name, attributes },
+                list_ids: value.list_ids,
+                source: source.unwrap_or_else(|| DEFAULT_CONSENT_SOURCE.into()),
+                consent_text_version
+            }),
+            _ => Err(errors)
+        }
+    }
+}
+
+/// Validation messages keyed by the name of the field they are about.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct FieldErrors(BTreeMap<&'static str, String>);
+
+impl FieldErrors {
+    fn check<T>(&mut self, field: &'static str, result: Result<T, String>) -> Option<T> {
+        result.map_err(|e| self.0.insert(field, e)).ok()
+    }
+
+    fn single(field: &'static str, message: String) -> Self {
+        Self(BTreeMap::from([(field, message)]))
+    }
+}
+
+impl std::fmt::Display for FieldErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let messages: Vec<&str> = self.0.values().map(String::as_str).collect();
+        write!(f, "{}", messages.join("\n"))
+    }
+}
+
+#[derive(serde::Serialize)]
+struct JsonErrorBody<'a> {
+    error: String,
+    fields: Option<&'a FieldErrors>
+}
+
+/// Source recorded when the form does not name itself.
+const DEFAULT_CONSENT_SOURCE: &str = "subscription_form";
+
+/// `source` and `consent_text_version` are short labels chosen by whoever wrote the form.
+fn parse_consent_label(value: Option<String>, field: &str) -> Result<Option<String>, String> {
+    let value = match value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty()) {
+        Some(value) => value,
+        None => return Ok(None)
+    };
+    if value.chars().count() > 64 || value.chars().any(char::is_control) {
+        return Err(format!("The {} must be at most 64 printable characters.", field));
+    }
+    Ok(Some(value))
+}
+
+const APPLICATION_JSON: &str = "application/json";
+const APPLICATION_WWW_FORM_URLENCODED: &str = "application/x-www-form-urlencoded";
+
+/// A JSON request gets a JSON response; so does a client that asks for one.
+fn wants_json(request: &HttpRequest) -> bool {
+    request.content_type() == APPLICATION_JSON || request
+        .headers()
+        .get(actix_web::http::header::ACCEPT)
+        .and_then(|h| h.to_str().ok())
+        .map(|accept| accept.contains(APPLICATION_JSON))
+        .unwrap_or(false)
+}
+
+fn parse_body(request: &HttpRequest, body: &[u8]) -> Result<FormData, SubscribeError> {
+    if request.content_type() == APPLICATION_JSON {
+        let data: JsonData = serde_json::from_slice(body)
+            .map_err(|e| SubscribeError::ValidationError(e.to_string()))?;
+        return Ok(data.into());
+    }
+    if request.content_type() != APPLICATION_WWW_FORM_URLENCODED {
+        return Err(SubscribeError::UnsupportedMediaType);
+    }
+    // `web::Form` cannot deserialize repeated keys, which is how
+    // a form submits several `list_id` checkboxes.
+    let mut form: FormData = serde_html_form::from_bytes(body)
+        .map_err(|e| SubscribeError::ValidationError(e.to_string()))?;
+    let fields: BTreeMap<String, Vec<String>> = serde_html_form::from_bytes(body)
+        .map_err(|e| SubscribeError::ValidationError(e.to_string()))?;
+    form.attributes = fields
+        .into_iter()
+        .filter_map(|(key, values)| {
+            Some((key.strip_prefix("attributes.")?.to_string(), values.into_iter().last()?))
+        })
+        .collect();
+    Ok(form)
+}
+
+/// Only scripts fill in the hidden `website` field.
+fn caught_by_honeypot(form: &FormData) -> bool {
+    form.website.as_deref().is_some_and(|website| !website.is_empty())
+}
+
+/// Checks the challenge the form was served with, when there is one or one is required.
+fn check_challenge(
+    form: &FormData,
+    settings: &BotProtectionSettings,
+    hmac_secret: &Secret<String>
+) -> Result<(), SubscribeError> {
+    let challenge = match form.challenge.as_deref().filter(|challenge| !challenge.is_empty()) {
+        Some(challenge) => challenge,
+        None if settings.require_challenge => {
+            return Err(SubscribeError::ValidationError(
+                "The form is missing its challenge. Please reload the page and try again.".into()
+            ));
+        },
+        None => return Ok(())
+    };
+    let nonce = form.nonce.as_deref().unwrap_or_default();
+    match SubscribeChallenge::verify(challenge, &form.email, nonce, &settings.challenge_rules(), Utc::now(), hmac_secret) {
+        Ok(()) => Ok(()),
+        // Someone who left the page open for too long, rather than a script.
+        Err(SubscribeChallengeError::Expired) => Err(SubscribeError::ValidationError(
+            "The form has expired. Please reload the page and try again.".into()
+        )),
+        Err(e) => {
+            tracing::warn!(target: "bot_protection", reason = %e, "Rejected a signup with a failed challenge.");
+            Err(SubscribeError::ValidationError(e.to_string()))
+        }
     }
 }
 
@@ -36,27 +232,154 @@ pub fn generate_subscription_token() -> String {
         .collect()
 }
 
+/// Only this digest is persisted, so read access to the database
+/// is not enough to confirm someone else's address.
+pub(crate) fn hash_subscription_token(subscription_token: &str) -> String {
+    hex::encode(Sha256::digest(subscription_token.as_bytes()))
+}
+
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip (form, pool, email_client, base_url),
+    skip (body, request, pool, email_client, email_policy, mx_checker, rate_limiter, bot_protection, hmac_secret, base_url),
     fields(
-        subscriber_email = % form.email,
-        subscriber_name = % form.name
+        subscriber_email = tracing::field::Empty,
+        subscriber_name = tracing::field::Empty
     )
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
-    form: web::Form<FormData>,
+    body: web::Bytes,
+    request: HttpRequest,
     pool: Data<PgPool>,
     email_client: Data<EmailClient>,
+    email_policy: Data<EmailPolicy>,
+    mx_checker: Data<MxChecker>,
+    rate_limiter: Data<RateLimiter>,
+    bot_protection: Data<BotProtectionSettings>,
+    hmac_secret: Data<HmacSecret>,
     base_url: Data<ApplicationBaseUrl>
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into()?;
+    let wants_json = wants_json(&request);
+    let result = async {
+        let form = parse_body(&request, &body)?;
+        if caught_by_honeypot(&form) {
+            tracing::warn!(target: "bot_protection", "Rejected a signup that filled in the honeypot field.");
+            // Answered like any other signup, so that the script learns nothing.
+            return Ok(());
+        }
+        check_challenge(&form, &bot_protection, &hmac_secret.0)?;
+        register_subscriber(form, &request, &pool, &email_client, &email_policy, &mx_checker, &rate_limiter, &base_url.0).await
+    }.await;
+    match result {
+        Ok(()) if wants_json => Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "accepted" }))),
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(e) if wants_json => match &e {
+            SubscribeError::ValidationError(message) => Ok(HttpResponse::BadRequest().json(JsonErrorBody {
+                error: message.clone(),
+                fields: None
+            })),
+            SubscribeError::InvalidFields(errors) => Ok(HttpResponse::BadRequest().json(JsonErrorBody {
+                error: "Some fields are invalid.".into(),
+                fields: Some(errors)
+            })),
+            SubscribeError::TooManyRequests(RetryAfter(seconds)) => Ok(HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, seconds.to_string()))
+                .json(JsonErrorBody {
+                    error: e.to_string(),
+                    fields: None
+                })),
+            SubscribeError::UnsupportedMediaType => Ok(HttpResponse::UnsupportedMediaType().json(JsonErrorBody {
+                error: e.to_string(),
+                fields: None
+            })),
+            SubscribeError::UnexpectedError(_) => Err(e)
+        },
+        Err(e) => Err(e)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn register_subscriber(
+    form: FormData,
+    request: &HttpRequest,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    email_policy: &EmailPolicy,
+    mx_checker: &MxChecker,
+    rate_limiter: &RateLimiter,
+    base_url: &str
+) -> Result<(), SubscribeError> {
+    if let Some(ip_address) = client_ip(request) {
+        rate_limiter.check_ip("subscriptions", &ip_address.to_string()).await.map_err(SubscribeError::TooManyRequests)?;
+    }
+    Span::current()
+        .record("subscriber_email", &display(&form.email))
+        .record("subscriber_name", &display(&form.name));
+    let Subscription {
+        subscriber: new_subscriber,
+        list_ids,
+        source,
+        consent_text_version
+    } = form.try_into().map_err(SubscribeError::InvalidFields)?;
+    email_policy
+        .check(&new_subscriber.email)
+        .map_err(|e| SubscribeError::InvalidFields(FieldErrors::single("email", e)))?;
+    let canonical_email = email_policy.canonical(&new_subscriber.email);
+    // Counted for valid addresses only: those are the ones that get an email.
+    rate_limiter
+        .check_email("subscriptions", &canonical_email)
+        .await
+        .map_err(SubscribeError::TooManyRequests)?;
+    mx_checker
+        .check(new_subscriber.email.domain(), new_subscriber.email.punycode_domain())
+        .await
+        .map_err(|e| SubscribeError::InvalidFields(FieldErrors::single("email", e.to_string())))?;
+    // A widget cannot vouch for its own `source`: attribution comes from the checked origin.
+    let source = match request.extensions().get::<EmbeddingSite>() {
+        Some(EmbeddingSite(site_id)) => format!("widget:{}", site_id),
+        None => source
+    };
+    let consent = ConsentContext::from_request(request, source, consent_text_version);
     let mut transaction = pool.begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let list_ids = resolve_list_ids(&mut transaction, list_ids).await?;
+    let (subscriber_id, is_confirmed) = match insert_subscriber(&mut transaction, &new_subscriber, &canonical_email, &consent.source)
+        .await
+        .context("Failed to insert new subscriber into the database.")?
+    {
+        Some(subscriber_id) => (subscriber_id, false),
+        None => {
+            let existing = get_existing_subscriber(&mut transaction, &new_subscriber, &canonical_email)
+                .await
+                .context("Failed to retrieve an existing subscriber.")?;
+            let status = match existing.status.resubscribed() {
+                Some(status) => status,
+                // Never emailed again, yet answered as any other signup.
+                None => return Ok(())
+            };
+            if status != existing.status {
+                restart_double_opt_in(&mut transaction, existing.id, &new_subscriber)
+                    .await
+                    .context("Failed to reset a subscriber who left.")?;
+            }
+            (existing.id, status == SubscriptionStatus::Confirmed)
+        }
+    };
+    let n_new_memberships = add_list_memberships(&mut transaction, subscriber_id, &list_ids)
+        .await
+        .context("Failed to add the subscriber to the requested lists.")?;
+    if is_confirmed && n_new_memberships == 0 {
+        // Respond exactly as for a new address, so that the endpoint
+        // does not reveal who is already on the list.
+        return Ok(());
+    }
+    record_consent_event(&mut transaction, subscriber_id, ConsentEventKind::Subscribed, &consent)
         .await
-        .context("Failed to insert new subscriber into the database.")?;
+        .context("Failed to record the consent of a subscriber.")?;
+    delete_tokens(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to delete stale confirmation tokens.")?;
     let subscription_token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &subscription_token)
         .await
@@ -66,21 +389,27 @@ pub async fn subscribe(
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
 
     send_confirmation_email(
-        &email_client,
+        email_client,
         new_subscriber,
-        &base_url.0,
+        base_url,
         &subscription_token
     )
     .await
     .context("Failed to send a confirmation email.")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(())
 }
 
 #[derive(thiserror::Error)]
 pub enum  SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("{0}")]
+    InvalidFields(FieldErrors),
+    #[error("Too many subscription requests. Please try again in {0}.")]
+    TooManyRequests(RetryAfter),
+    #[error("Subscriptions are accepted as a form or as JSON.")]
+    UnsupportedMediaType,
     #[error(transparent)]
     UnexpectedError(#[from]anyhow::Error)
 }
@@ -93,10 +422,21 @@ impl std::fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError(_)
+            | SubscribeError::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            SubscribeError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let SubscribeError::TooManyRequests(RetryAfter(seconds)) = self {
+            response.insert_header((header::RETRY_AFTER, seconds.to_string()));
+        }
+        response.content_type("text/plain; charset=utf-8").body(self.to_string())
+    }
 }
 impl From<String> for SubscribeError {
     fn from(e: String) -> Self {
@@ -108,15 +448,15 @@ impl From<String> for SubscribeError {
     name = "Store subscription token in the database",
     skip(subscription_token, transaction)
 )]
-async fn store_token(
+pub(crate) async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
     subscription_token: &str
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens(subscription_token, subscriber_id)
+        r#"INSERT INTO subscription_tokens(subscription_token_hash, subscriber_id)
         VALUES($1, $2)"#,
-        subscription_token,
+        hash_subscription_token(subscription_token),
         subscriber_id
     )
     .execute(transaction)
@@ -166,7 +506,7 @@ pub fn error_chain_fmt(
     name = "Send a confirmation email to a new subscriber",
     skip(email_client, new_subscriber, base_url, subscription_token)
 )]
-async fn send_confirmation_email(email_client: &Data<EmailClient>,
+pub(crate) async fn send_confirmation_email(email_client: &EmailClient,
                                  new_subscriber: NewSubscriber,
                                  base_url: &str,
                                  subscription_token: &str
@@ -187,6 +527,7 @@ async fn send_confirmation_email(email_client: &Data<EmailClient>,
         ).await
 }
 
+/// Returns `None` if a subscriber with the same email address already exists.
 #[tracing::instrument(
     name = "Saving new subscriber details to database.",
     skip(new_subscriber, transaction)
@@ -194,20 +535,179 @@ async fn send_confirmation_email(email_client: &Data<EmailClient>,
 async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
+    canonical_email: &str,
+    signup_source: &str
+) -> Result<Option<Uuid>, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
+    let status = SubscriptionStatus::PendingConfirmation;
 
-    sqlx::query!(
+    let n_inserted_rows = sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email,name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (
+            id, email, punycode_email, name, subscribed_at, status, attributes, signup_source, canonical_email
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT DO NOTHING
       "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
+        new_subscriber.email.punycode(),
+        new_subscriber.name.as_ref(),
+        Utc::now(),
+        status as SubscriptionStatus,
+        new_subscriber.attributes.to_json(),
+        signup_source,
+        canonical_email
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if n_inserted_rows > 0 {
+        record_initial_status(transaction, subscriber_id, status, signup_source).await?;
+        Ok(Some(subscriber_id))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Falls back to the default list when the form does not name any.
+#[tracing::instrument(
+    name = "Resolve the lists to subscribe to",
+    skip(transaction)
+)]
+async fn resolve_list_ids(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_ids: Vec<Uuid>,
+) -> Result<Vec<Uuid>, SubscribeError> {
+    if list_ids.is_empty() {
+        let default_list = sqlx::query!(r#"SELECT list_id FROM lists WHERE is_default"#)
+            .fetch_optional(transaction)
+            .await
+            .context("Failed to retrieve the default list.")?;
+        return default_list
+            .map(|r| vec![r.list_id])
+            .ok_or_else(|| SubscribeError::ValidationError("Please choose at least one list.".into()));
+    }
+
+    let known_list_ids: Vec<Uuid> = sqlx::query!(
+        r#"SELECT list_id FROM lists WHERE list_id = ANY($1)"#,
+        &list_ids[..]
+    )
+    .fetch_all(transaction)
+    .await
+    .context("Failed to retrieve the requested lists.")?
+    .into_iter()
+    .map(|r| r.list_id)
+    .collect();
+    if let Some(unknown) = list_ids.iter().find(|id| !known_list_ids.contains(id)) {
+        return Err(SubscribeError::ValidationError(format!("{} is not a known list.", unknown)));
+    }
+    Ok(known_list_ids)
+}
+
+/// Returns how many memberships are newly awaiting confirmation.
+#[tracing::instrument(
+    name = "Add subscriber to lists",
+    skip(transaction)
+)]
+async fn add_list_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<u64, sqlx::Error> {
+    let n_affected_rows = sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status)
+        SELECT list_id, $2, $3
+        FROM UNNEST($1::uuid[]) AS list_id
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = $3
+        WHERE list_memberships.status = $4
+        "#,
+        list_ids,
+        subscriber_id,
+        SubscriptionStatus::PendingConfirmation as SubscriptionStatus,
+        SubscriptionStatus::Unsubscribed as SubscriptionStatus
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+    Ok(n_affected_rows)
+}
+
+struct ExistingSubscriber {
+    id: Uuid,
+    status: SubscriptionStatus
+}
+
+#[tracing::instrument(
+    name = "Get existing subscriber details from the database.",
+    skip(new_subscriber, transaction)
+)]
+async fn get_existing_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    canonical_email: &str
+) -> Result<ExistingSubscriber, sqlx::Error> {
+    // Rows from before canonical addresses were recorded may only match on the address itself.
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"
+        SELECT id, status AS "status: SubscriptionStatus" FROM subscriptions
+        WHERE canonical_email = $1 OR email = $2
+        ORDER BY canonical_email = $1 DESC NULLS LAST
+        LIMIT 1
+        FOR UPDATE
+        "#,
+        canonical_email,
+        new_subscriber.email.as_ref()
+    )
+    .fetch_one(transaction)
+    .await
+}
+
+#[tracing::instrument(
+    name = "Restart double opt-in for a subscriber who left.",
+    skip(new_subscriber, transaction)
+)]
+async fn restart_double_opt_in(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    new_subscriber: &NewSubscriber,
+) -> Result<(), StatusTransitionError> {
+    transition_status(transaction, subscriber_id, SubscriptionStatus::PendingConfirmation, "resubscribed").await?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            name = $2,
+            subscribed_at = $3,
+            attributes = attributes || $4
+        WHERE id = $1
+        "#,
+        subscriber_id,
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        new_subscriber.attributes.to_json()
     )
     .execute(transaction)
     .await?;
-    Ok(subscriber_id)
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Delete confirmation tokens of a subscriber",
+    skip(transaction)
+)]
+pub(crate) async fn delete_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
 }
@@ -0,0 +1,29 @@
+use crate::configuration::BotProtectionSettings;
+use crate::domain::SubscribeChallenge;
+use crate::startup::HmacSecret;
+use actix_web::{web, HttpResponse};
+use actix_web::http::header;
+use chrono::Utc;
+
+#[derive(serde::Serialize)]
+struct ChallengeBody {
+    challenge: String,
+    difficulty: u32
+}
+
+/// Fetched by the subscribe form when it is displayed; the form sends the
+/// challenge back, together with a proof-of-work nonce when `difficulty` is not 0.
+/// JSON API clients follow the same steps, as described in the README.
+#[tracing::instrument(name = "Issue a subscribe challenge", skip(bot_protection, hmac_secret))]
+pub async fn subscribe_challenge(
+    bot_protection: web::Data<BotProtectionSettings>,
+    hmac_secret: web::Data<HmacSecret>
+) -> HttpResponse {
+    let challenge = SubscribeChallenge::issue(Utc::now(), &hmac_secret.0);
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(ChallengeBody {
+            challenge: challenge.as_ref().to_owned(),
+            difficulty: bot_protection.proof_of_work_difficulty
+        })
+}
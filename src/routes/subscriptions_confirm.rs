@@ -1,9 +1,21 @@
 use std::fmt::Formatter;
-use actix_web::{HttpResponse, web};
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web::body::BoxBody;
+use actix_web::http::header::ContentType;
 use actix_web::http::StatusCode;
-use sqlx::PgPool;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
+use crate::domain::{NewSubscriber, SubscriberAttributes, SubscriberEmail, SubscriberName, SubscriptionStatus};
+use crate::email_client::EmailClient;
+use crate::routes::consent::{record_consent_event, ConsentContext, ConsentEventKind};
 use crate::routes::error_chain_fmt;
+use crate::routes::subscription_status::{transition_status, StatusTransitionError};
+use crate::routes::subscriptions::{delete_tokens, hash_subscription_token, send_confirmation_email, store_token};
+use crate::routes::generate_subscription_token;
+use crate::startup::{ApplicationBaseUrl, SubscriptionTokenTtl};
+use crate::welcome_email::enqueue_welcome_email;
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
@@ -14,8 +26,12 @@ pub struct Parameters {
 pub enum ConfirmError {
     #[error("The subscription token is invalid.")]
     TokenDoesNotExist,
+    #[error("The subscription token has expired.")]
+    TokenExpired(String),
+    #[error("The subscription can no longer be confirmed.")]
+    CannotBeConfirmed,
     #[error(transparent)]
-    UnexpectedError(#[from]sqlx::Error)
+    UnexpectedError(#[from]anyhow::Error)
 }
 impl std::fmt::Debug for ConfirmError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -26,58 +42,249 @@ impl actix_web::ResponseError for ConfirmError {
     fn status_code(&self) -> StatusCode {
         match self {
             Self::TokenDoesNotExist => StatusCode::UNAUTHORIZED,
+            Self::TokenExpired(_) => StatusCode::GONE,
+            Self::CannotBeConfirmed => StatusCode::CONFLICT,
             Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR
         }
     }
+
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        let body = match self {
+            Self::TokenDoesNotExist => "<p>This confirmation link is invalid or has already been used.</p>".to_string(),
+            Self::TokenExpired(subscription_token) => {
+                let subscription_token = htmlescape::encode_attribute(subscription_token);
+                format!(r#"<p>This confirmation link has expired.</p>
+                    <form action="/subscriptions/confirm/resend" method="post">
+                        <input hidden type="text" name="subscription_token" value="{subscription_token}">
+                        <button type="submit">Send me a new confirmation email</button>
+                    </form>"#)
+            }
+            Self::CannotBeConfirmed => "<p>This subscription can no longer be confirmed. Please subscribe again.</p>".to_string(),
+            Self::UnexpectedError(_) => "<p>Something went wrong. Please try again later.</p>".to_string()
+        };
+        HttpResponse::build(self.status_code())
+            .content_type(ContentType::html())
+            .body(confirmation_page("Subscription confirmation", &body))
+    }
+}
+
+fn confirmation_page(title: &str, body: &str) -> String {
+    format!(r#"<!DOCTYPE html><html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>{title}</title>
+    </head>
+    <body>
+        {body}
+    </body>
+    </html>
+    "#)
 }
 
 
+/// Landing page for the emailed link. It never changes state, so that link
+/// scanners and prefetchers cannot confirm an address on the owner's behalf.
+#[tracing::instrument(
+    name = "Show the subscription confirmation page"
+    skip(parameters, pool, subscription_token_ttl)
+)]
+pub async fn confirm_form(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    subscription_token_ttl: web::Data<SubscriptionTokenTtl>
+) -> Result<HttpResponse, ConfirmError> {
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    get_subscriber_id_from_token(
+        &mut transaction,
+        &parameters.subscription_token,
+        subscription_token_ttl.0
+    ).await?;
+    transaction.rollback()
+        .await
+        .context("Failed to roll back a read-only SQL transaction.")?;
+
+    let subscription_token = htmlescape::encode_attribute(&parameters.subscription_token);
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(confirmation_page(
+        "Confirm your subscription",
+        &format!(r#"<p>Please confirm that you want to receive our newsletter.</p>
+        <form action="/subscriptions/confirm" method="post">
+            <input hidden type="text" name="subscription_token" value="{subscription_token}">
+            <button type="submit">Confirm my subscription</button>
+        </form>"#)
+    )))
+}
+
 #[tracing::instrument(
     name = "Confirm a pending subscriber"
-    skip(parameters, pool)
+    skip(form, request, pool, subscription_token_ttl)
 )]
 pub async fn confirm(
-    parameters: web::Query<Parameters>,
-    pool: web::Data<PgPool>
+    form: web::Form<Parameters>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    subscription_token_ttl: web::Data<SubscriptionTokenTtl>
+) -> Result<HttpResponse, ConfirmError> {
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let id = get_subscriber_id_from_token(
+        &mut transaction,
+        &form.subscription_token,
+        subscription_token_ttl.0
+    ).await?;
+    let consent = ConsentContext::from_request(&request, "confirmation_link", None);
+    confirm_subscriber(&mut transaction, id, &consent)
+        .await
+        .map_err(|e| match e {
+            StatusTransitionError::UnknownSubscriber(_) => ConfirmError::TokenDoesNotExist,
+            StatusTransitionError::Invalid(_) => ConfirmError::CannotBeConfirmed,
+            e => anyhow::Error::new(e).context("Failed to mark the subscriber as confirmed.").into()
+        })?;
+    // Tokens are single-use: once confirmed, no link for this subscriber works anymore.
+    delete_tokens(&mut transaction, id)
+        .await
+        .context("Failed to delete used confirmation tokens.")?;
+    transaction.commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(confirmation_page(
+        "Subscription confirmed",
+        "<p>Thank you! Your subscription is confirmed.</p>"
+    )))
+}
+
+#[tracing::instrument(
+    name = "Resend a confirmation email"
+    skip(form, pool, email_client, base_url)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<Parameters>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>
 ) -> Result<HttpResponse, ConfirmError> {
-    let id = get_subscriber_id_from_token(&pool, &parameters.subscription_token).await?;
-    confirm_subscriber(&pool, id).await?;
-    Ok(HttpResponse::Ok().finish())
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let (subscriber_id, subscriber) = get_pending_subscriber_from_token(
+        &mut transaction,
+        &form.subscription_token
+    ).await?;
+    delete_tokens(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to delete stale confirmation tokens.")?;
+    let subscription_token = generate_subscription_token();
+    store_token(&mut transaction, subscriber_id, &subscription_token)
+        .await
+        .context("Failed to store a new confirmation token.")?;
+    transaction.commit()
+        .await
+        .context("Failed to commit SQL transaction to rotate a confirmation token.")?;
+
+    send_confirmation_email(&email_client, subscriber, &base_url.0, &subscription_token)
+        .await
+        .context("Failed to send a confirmation email.")?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(confirmation_page(
+        "Confirmation email sent",
+        "<p>We have sent you a new confirmation email.</p>"
+    )))
 }
 
 #[tracing::instrument(
     name = "Mark subscriber as confirmed",
-    skip(pool, subscriber_id)
+    skip(transaction, subscriber_id, consent)
 )]
-async fn confirm_subscriber(
-    pool: &PgPool,
-    subscriber_id: Uuid
-) -> Result<(), ConfirmError> {
+pub(crate) async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    consent: &ConsentContext
+) -> Result<(), StatusTransitionError> {
+    // Already confirmed subscribers come through here to confirm the lists they added.
+    let previous_status = transition_status(transaction, subscriber_id, SubscriptionStatus::Confirmed, &consent.source).await?;
+    if previous_status != SubscriptionStatus::Confirmed {
+        enqueue_welcome_email(transaction, subscriber_id).await?;
+    }
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id
+        r#"
+        UPDATE list_memberships SET status = $2
+        WHERE subscriber_id = $1 AND status = $3
+        "#,
+        subscriber_id,
+        SubscriptionStatus::Confirmed as SubscriptionStatus,
+        SubscriptionStatus::PendingConfirmation as SubscriptionStatus
     )
-    .execute(pool)
+    .execute(&mut *transaction)
     .await?;
+    record_consent_event(transaction, subscriber_id, ConsentEventKind::Confirmed, consent).await?;
     Ok(())
 }
 
 #[tracing::instrument(
     name = "Get subscriber_id from token",
-    skip(subscription_token, pool)
+    skip(transaction, subscription_token, ttl)
 )]
 async fn get_subscriber_id_from_token(
-    pool: &PgPool,
-    subscription_token: &String
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str,
+    ttl: chrono::Duration
 ) -> Result<Uuid, ConfirmError> {
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
-        subscription_token
+        r#"
+        SELECT t.subscriber_id, t.created_at, s.status AS "status: SubscriptionStatus"
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token_hash = $1
+        FOR UPDATE
+        "#,
+        hash_subscription_token(subscription_token)
     )
-    .fetch_optional(pool)
-    .await?;
-    match result.map(|r| r.subscriber_id) {
-        Some(subscriber_id) => Ok(subscriber_id),
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to retrieve the confirmation token.")?;
+    match result {
+        // A subscriber who unsubscribed or bounced since must sign up again rather than
+        // be brought back by a link from before.
+        Some(r) if r.status != SubscriptionStatus::Confirmed && !r.status.can_transition_to(SubscriptionStatus::Confirmed) => {
+            Err(ConfirmError::CannotBeConfirmed)
+        }
+        Some(r) if r.created_at + ttl < Utc::now() => {
+            Err(ConfirmError::TokenExpired(subscription_token.to_owned()))
+        }
+        Some(r) => Ok(r.subscriber_id),
         None => Err(ConfirmError::TokenDoesNotExist)
     }
-}
\ No newline at end of file
+}
+
+#[tracing::instrument(
+    name = "Get pending subscriber from token",
+    skip(transaction, subscription_token)
+)]
+async fn get_pending_subscriber_from_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &str
+) -> Result<(Uuid, NewSubscriber), ConfirmError> {
+    let result = sqlx::query!(
+        r#"
+        SELECT s.id, s.email, s.punycode_email, s.name, s.attributes
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token_hash = $1 AND s.status = $2
+        FOR UPDATE
+        "#,
+        hash_subscription_token(subscription_token),
+        SubscriptionStatus::PendingConfirmation as SubscriptionStatus
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to retrieve the subscriber of a confirmation token.")?
+    .ok_or(ConfirmError::TokenDoesNotExist)?;
+    let subscriber = NewSubscriber {
+        email: SubscriberEmail::from_stored(result.email, result.punycode_email).map_err(|e| anyhow::anyhow!(e))?,
+        name: SubscriberName::parse(result.name).map_err(|e| anyhow::anyhow!(e))?,
+        attributes: SubscriberAttributes::from_json(result.attributes)
+    };
+    Ok((result.id, subscriber))
+}
@@ -0,0 +1,455 @@
+use std::fmt::Formatter;
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web::http::header::{self, ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::client_ip::client_ip;
+use crate::domain::{DataExportToken, DataExportTokenError, EmailPolicy, SubscriberEmail, SubscriptionStatus};
+use crate::email_client::EmailClient;
+use crate::rate_limit::{RateLimiter, RetryAfter};
+use crate::routes::consent::get_consent_events;
+use crate::routes::error_chain_fmt;
+use crate::routes::subscription_status::get_status_history;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+
+/// How long an emailed download link stays valid.
+const DATA_EXPORT_LINK_TTL_HOURS: i64 = 24;
+
+#[derive(serde::Deserialize)]
+pub struct DataExportRequest {
+    email: String
+}
+
+#[derive(serde::Deserialize)]
+pub struct DownloadParameters {
+    subscriber_id: Uuid,
+    expires_at: i64,
+    token: String
+}
+
+#[derive(thiserror::Error)]
+pub enum DataExportError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The download link is invalid.")]
+    InvalidLink(#[source] DataExportTokenError),
+    #[error("The download link has expired.")]
+    LinkExpired,
+    #[error("Too many data export requests. Please try again in {0}.")]
+    TooManyRequests(RetryAfter),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error)
+}
+impl std::fmt::Debug for DataExportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+impl actix_web::ResponseError for DataExportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidLink(_) => StatusCode::UNAUTHORIZED,
+            Self::LinkExpired => StatusCode::GONE,
+            Self::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let Self::TooManyRequests(RetryAfter(seconds)) = self {
+            response.insert_header((header::RETRY_AFTER, seconds.to_string()));
+        }
+        response.content_type("text/plain; charset=utf-8").body(self.to_string())
+    }
+}
+impl From<DataExportTokenError> for DataExportError {
+    fn from(e: DataExportTokenError) -> Self {
+        match e {
+            DataExportTokenError::Expired => Self::LinkExpired,
+            e => Self::InvalidLink(e)
+        }
+    }
+}
+
+/// Everything stored about one subscriber, as handed out for a data-subject access request.
+#[derive(serde::Serialize)]
+pub struct DataExport {
+    generated_at: String,
+    subscriber: ExportedProfile,
+    consent: ExportedConsent,
+    status_history: Vec<ExportedStatusChange>,
+    lists: Vec<ExportedMembership>,
+    tags: Vec<ExportedTag>,
+    delivered_issues: Vec<ExportedDelivery>,
+    pending_deliveries: Vec<ExportedPendingDelivery>
+}
+
+#[derive(serde::Serialize)]
+struct ExportedProfile {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: SubscriptionStatus,
+    attributes: serde_json::Value
+}
+
+#[derive(serde::Serialize)]
+struct ExportedConsent {
+    subscribed_at: String,
+    /// Confirmation emails sent and not used yet.
+    pending_confirmation_requests: Vec<String>,
+    events: Vec<ExportedConsentEvent>
+}
+
+#[derive(serde::Serialize)]
+struct ExportedConsentEvent {
+    event: String,
+    source: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    consent_text_version: Option<String>,
+    occurred_at: String
+}
+
+#[derive(serde::Serialize)]
+struct ExportedStatusChange {
+    from: Option<SubscriptionStatus>,
+    to: SubscriptionStatus,
+    reason: String,
+    changed_at: String
+}
+
+#[derive(serde::Serialize)]
+struct ExportedMembership {
+    list: String,
+    status: SubscriptionStatus,
+    joined_at: String
+}
+
+#[derive(serde::Serialize)]
+struct ExportedTag {
+    tag: String,
+    tagged_at: String
+}
+
+#[derive(serde::Serialize)]
+struct ExportedDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    delivered_at: String
+}
+
+#[derive(serde::Serialize)]
+struct ExportedPendingDelivery {
+    newsletter_issue_id: Uuid,
+    title: String
+}
+
+fn rfc3339(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Always answers the same way, so that the form does not reveal who is on the list.
+#[tracing::instrument(
+    name = "Request a data export",
+    skip(form, request, pool, email_client, email_policy, rate_limiter, base_url, hmac_secret)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn request_data_export(
+    form: web::Form<DataExportRequest>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    email_policy: web::Data<EmailPolicy>,
+    rate_limiter: web::Data<RateLimiter>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>
+) -> Result<HttpResponse, DataExportError> {
+    if let Some(ip_address) = client_ip(&request) {
+        rate_limiter.check_ip("data_export", &ip_address.to_string()).await.map_err(DataExportError::TooManyRequests)?;
+    }
+    let email = SubscriberEmail::parse(form.0.email).map_err(DataExportError::ValidationError)?;
+    // Counted whether or not the address is on the list, which would otherwise show.
+    rate_limiter
+        .check_email("data_export", &email_policy.canonical(&email))
+        .await
+        .map_err(DataExportError::TooManyRequests)?;
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT id, email, punycode_email FROM subscriptions
+        WHERE canonical_email = $1 OR email = $2
+        ORDER BY canonical_email = $1 DESC NULLS LAST
+        LIMIT 1
+        "#,
+        email_policy.canonical(&email),
+        email.as_ref()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to look up a subscriber by email.")?;
+    if let Some(subscriber) = subscriber {
+        // The link goes to the address on file, never to the spelling that was typed:
+        // an alias of the mailbox must not be a way to read someone else's data.
+        let email = SubscriberEmail::from_stored(subscriber.email, subscriber.punycode_email)
+            .map_err(|e| anyhow::anyhow!(e))?;
+        // A failure is not reported either: only known addresses get this far.
+        if let Err(e) = send_data_export_email(&email_client, &email, &base_url.0, subscriber.id, &hmac_secret.0).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a data export email."
+            );
+        }
+    }
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        format!(r#"<!DOCTYPE html><html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Data export requested</title>
+        </head>
+        <body>
+            <p>If this address is in our records, we have sent it a link to download your data.
+            The link is valid for {DATA_EXPORT_LINK_TTL_HOURS} hours.</p>
+        </body>
+        </html>
+        "#)
+    ))
+}
+
+#[tracing::instrument(
+    name = "Download a data export",
+    skip(parameters, pool, hmac_secret),
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn download_data_export(
+    parameters: web::Query<DownloadParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>
+) -> Result<HttpResponse, DataExportError> {
+    let DownloadParameters { subscriber_id, expires_at, token } = parameters.into_inner();
+    DataExportToken::verify(&token, subscriber_id, expires_at, Utc::now(), &hmac_secret.0)?;
+    // The subscriber may have been deleted since the link was sent.
+    let export = get_data_export(&pool, subscriber_id)
+        .await?
+        .ok_or(DataExportError::LinkExpired)?;
+    Ok(data_export_response(&export))
+}
+
+pub(crate) fn data_export_link(
+    base_url: &str,
+    subscriber_id: Uuid,
+    hmac_secret: &Secret<String>
+) -> String {
+    let expires_at = (Utc::now() + Duration::hours(DATA_EXPORT_LINK_TTL_HOURS)).timestamp();
+    let token = DataExportToken::generate(subscriber_id, expires_at, hmac_secret);
+    format!(
+        "{}/subscriptions/data-export?subscriber_id={}&expires_at={}&token={}",
+        base_url,
+        subscriber_id,
+        expires_at,
+        token.as_ref()
+    )
+}
+
+#[tracing::instrument(
+    name = "Send a data export link",
+    skip(email_client, email, base_url, hmac_secret)
+)]
+pub(crate) async fn send_data_export_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    subscriber_id: Uuid,
+    hmac_secret: &Secret<String>
+) -> Result<(), reqwest::Error> {
+    let link = data_export_link(base_url, subscriber_id, hmac_secret);
+    let html_body = format!(
+        "You asked for a copy of the data we hold about you.<br/> \
+        Click <a href=\"{}\">here</a> to download it. The link is valid for {} hours.",
+        link,
+        DATA_EXPORT_LINK_TTL_HOURS
+    );
+    let plain_body = format!(
+        "You asked for a copy of the data we hold about you. \n Visit {} to download it. \
+        The link is valid for {} hours.",
+        link,
+        DATA_EXPORT_LINK_TTL_HOURS
+    );
+    email_client
+        .send_email(email, "Your data", &html_body, &plain_body)
+        .await
+}
+
+pub(crate) fn data_export_response(export: &DataExport) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscriber-data.json".into())]
+        })
+        .json(export)
+}
+
+/// Returns `None` if there is no such subscriber.
+#[tracing::instrument(name = "Assemble a data export", skip(pool))]
+pub(crate) async fn get_data_export(
+    pool: &PgPool,
+    subscriber_id: Uuid
+) -> Result<Option<DataExport>, anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT id, email, name, status AS "status: SubscriptionStatus", subscribed_at, attributes
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a subscriber.")?;
+    let subscriber = match subscriber {
+        Some(subscriber) => subscriber,
+        None => return Ok(None)
+    };
+
+    let pending_confirmation_requests = sqlx::query!(
+        r#"
+        SELECT created_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        ORDER BY created_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the confirmation requests of a subscriber.")?
+    .into_iter()
+    .map(|r| rfc3339(r.created_at))
+    .collect();
+
+    let consent_events = get_consent_events(pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the consent events of a subscriber.")?
+        .into_iter()
+        .map(|e| ExportedConsentEvent {
+            event: e.event,
+            source: e.source,
+            ip_address: e.ip_address,
+            user_agent: e.user_agent,
+            consent_text_version: e.consent_text_version,
+            occurred_at: rfc3339(e.occurred_at)
+        })
+        .collect();
+
+    let status_history = get_status_history(pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the status history of a subscriber.")?
+        .into_iter()
+        .map(|c| ExportedStatusChange {
+            from: c.from_status,
+            to: c.to_status,
+            reason: c.reason,
+            changed_at: rfc3339(c.changed_at)
+        })
+        .collect();
+
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.name, m.status AS "status: SubscriptionStatus", m.created_at
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        WHERE m.subscriber_id = $1
+        ORDER BY m.created_at, l.name
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the lists of a subscriber.")?
+    .into_iter()
+    .map(|r| ExportedMembership { list: r.name, status: r.status, joined_at: rfc3339(r.created_at) })
+    .collect();
+
+    let tags = sqlx::query!(
+        r#"
+        SELECT t.name, st.created_at
+        FROM subscriber_tags st
+        JOIN tags t ON t.tag_id = st.tag_id
+        WHERE st.subscriber_id = $1
+        ORDER BY st.created_at, t.name
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the tags of a subscriber.")?
+    .into_iter()
+    .map(|r| ExportedTag { tag: r.name, tagged_at: rfc3339(r.created_at) })
+    .collect();
+
+    let delivered_issues = sqlx::query!(
+        r#"
+        SELECT d.newsletter_issue_id, i.title, d.delivered_at
+        FROM issue_deliveries d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        WHERE d.subscriber_id = $1
+        ORDER BY d.delivered_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the issues delivered to a subscriber.")?
+    .into_iter()
+    .map(|r| ExportedDelivery {
+        newsletter_issue_id: r.newsletter_issue_id,
+        title: r.title,
+        delivered_at: rfc3339(r.delivered_at)
+    })
+    .collect();
+
+    let pending_deliveries = sqlx::query!(
+        r#"
+        SELECT q.newsletter_issue_id, i.title
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE q.subscriber_email = $1
+        ORDER BY i.title
+        "#,
+        subscriber.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the pending deliveries of a subscriber.")?
+    .into_iter()
+    .map(|r| ExportedPendingDelivery { newsletter_issue_id: r.newsletter_issue_id, title: r.title })
+    .collect();
+
+    Ok(Some(DataExport {
+        generated_at: rfc3339(Utc::now()),
+        consent: ExportedConsent {
+            subscribed_at: rfc3339(subscriber.subscribed_at),
+            pending_confirmation_requests,
+            events: consent_events
+        },
+        subscriber: ExportedProfile {
+            id: subscriber.id,
+            email: subscriber.email,
+            name: subscriber.name,
+            status: subscriber.status,
+            attributes: subscriber.attributes
+        },
+        status_history,
+        lists,
+        tags,
+        delivered_issues,
+        pending_deliveries
+    }))
+}
@@ -0,0 +1,183 @@
+use std::fmt::Formatter;
+use actix_web::{HttpResponse, web};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::domain::{SubscriptionStatus, UnsubscribeToken};
+use crate::routes::error_chain_fmt;
+use crate::routes::subscription_status::{transition_status, StatusTransitionError};
+use crate::routes::subscriptions::delete_tokens;
+use crate::startup::HmacSecret;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: Uuid,
+    token: String
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe link is invalid.")]
+    InvalidToken(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error)
+}
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+impl actix_web::ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidToken(_) => StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+pub fn unsubscribe_link(
+    base_url: &str,
+    subscriber_id: Uuid,
+    hmac_secret: &Secret<String>
+) -> String {
+    format!(
+        "{}/subscriptions/unsubscribe?{}",
+        base_url,
+        signed_query(subscriber_id, hmac_secret)
+    )
+}
+
+/// Target of the RFC 8058 `List-Unsubscribe` header: mail providers POST to it directly.
+pub fn one_click_unsubscribe_link(
+    base_url: &str,
+    subscriber_id: Uuid,
+    hmac_secret: &Secret<String>
+) -> String {
+    format!(
+        "{}/subscriptions/unsubscribe/one-click?{}",
+        base_url,
+        signed_query(subscriber_id, hmac_secret)
+    )
+}
+
+fn signed_query(subscriber_id: Uuid, hmac_secret: &Secret<String>) -> String {
+    let token = UnsubscribeToken::generate(subscriber_id, hmac_secret);
+    format!("subscriber_id={}&token={}", subscriber_id, token.as_ref())
+}
+
+#[tracing::instrument(
+    name = "Show the unsubscribe confirmation page",
+    skip(parameters, hmac_secret),
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    hmac_secret: web::Data<HmacSecret>
+) -> Result<HttpResponse, UnsubscribeError> {
+    let UnsubscribeParameters { subscriber_id, token } = parameters.0;
+    UnsubscribeToken::verify(&token, subscriber_id, &hmac_secret.0)
+        .map_err(UnsubscribeError::InvalidToken)?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        format!(r#"<!DOCTYPE html><html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Unsubscribe</title>
+        </head>
+        <body>
+            <p>Do you want to stop receiving our newsletter?</p>
+            <form action="/subscriptions/unsubscribe" method="post">
+                <input hidden type="text" name="subscriber_id" value="{subscriber_id}">
+                <input hidden type="text" name="token" value="{token}">
+                <button type="submit">Unsubscribe</button>
+            </form>
+        </body>
+        </html>
+        "#)
+    ))
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(form, pool, hmac_secret),
+    fields(subscriber_id = %form.subscriber_id)
+)]
+pub async fn unsubscribe(
+    form: web::Form<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>
+) -> Result<HttpResponse, UnsubscribeError> {
+    UnsubscribeToken::verify(&form.token, form.subscriber_id, &hmac_secret.0)
+        .map_err(UnsubscribeError::InvalidToken)?;
+    mark_subscriber_as_unsubscribed(&pool, form.subscriber_id, "unsubscribe_link")
+        .await
+        .context("Failed to mark the subscriber as unsubscribed.")?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html><html lang="en">
+        <head>
+            <meta http-equiv="content-type" content="text/html; charset=utf-8">
+            <title>Unsubscribed</title>
+        </head>
+        <body>
+            <p>You have been unsubscribed. You will not receive any further issues.</p>
+        </body>
+        </html>
+        "#
+    ))
+}
+
+/// RFC 8058 one-click unsubscribe. The signed parameters travel in the query string
+/// and the body only carries `List-Unsubscribe=One-Click`, so no session or form
+/// token is involved.
+#[tracing::instrument(
+    name = "One-click unsubscribe",
+    skip(parameters, pool, hmac_secret),
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn unsubscribe_one_click(
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>
+) -> Result<HttpResponse, UnsubscribeError> {
+    UnsubscribeToken::verify(&parameters.token, parameters.subscriber_id, &hmac_secret.0)
+        .map_err(UnsubscribeError::InvalidToken)?;
+    mark_subscriber_as_unsubscribed(&pool, parameters.subscriber_id, "one_click_unsubscribe")
+        .await
+        .context("Failed to mark the subscriber as unsubscribed.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(
+    name = "Mark subscriber as unsubscribed",
+    skip(pool)
+)]
+pub(crate) async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    reason: &str
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    match transition_status(&mut transaction, subscriber_id, SubscriptionStatus::Unsubscribed, reason).await {
+        // Signed links outlive erased subscribers; bounced and complained
+        // addresses are not emailed either, and keep the status that says why.
+        Ok(_) | Err(StatusTransitionError::UnknownSubscriber(_) | StatusTransitionError::Invalid(_)) => {},
+        Err(StatusTransitionError::Database(e)) => return Err(e)
+    }
+    // The signed link is not tied to a list, so it leaves all of them.
+    sqlx::query!(
+        r#"UPDATE list_memberships SET status = $2 WHERE subscriber_id = $1"#,
+        subscriber_id,
+        SubscriptionStatus::Unsubscribed as SubscriptionStatus
+    )
+    .execute(&mut transaction)
+    .await?;
+    // A confirmation link sent before leaving must not bring the subscriber back.
+    delete_tokens(&mut transaction, subscriber_id).await?;
+    transaction.commit().await?;
+    Ok(())
+}
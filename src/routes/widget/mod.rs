@@ -0,0 +1,96 @@
+use crate::configuration::WidgetSite;
+use crate::startup::ApplicationBaseUrl;
+use std::collections::HashMap;
+use actix_web::{HttpMessage, HttpResponse, web};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web_lab::middleware::Next;
+
+/// Origins allowed to post to `/subscriptions` from a browser.
+pub struct WidgetOrigins {
+    own_origin: String,
+    /// Site id by origin.
+    sites: HashMap<String, String>
+}
+
+impl WidgetOrigins {
+    pub fn new(base_url: &str, sites: Vec<WidgetSite>) -> Result<Self, anyhow::Error> {
+        let sites = sites
+            .into_iter()
+            .map(|site| Ok((normalise_origin(&site.origin)?, site.id)))
+            .collect::<Result<_, anyhow::Error>>()?;
+        Ok(Self {
+            own_origin: normalise_origin(base_url)?,
+            sites
+        })
+    }
+}
+
+fn normalise_origin(url: &str) -> Result<String, anyhow::Error> {
+    let url = reqwest::Url::parse(url)
+        .map_err(|e| anyhow::anyhow!("{} is not a valid origin: {}", url, e))?;
+    Ok(url.origin().ascii_serialization())
+}
+
+/// The allow-listed site a request was sent from.
+#[derive(Clone, Debug)]
+pub struct EmbeddingSite(pub String);
+
+/// Lets requests without an `Origin`, or from our own pages, through untouched.
+/// Requests from an allow-listed site are tagged with the site and get CORS headers;
+/// any other origin is turned away before the handler runs.
+pub async fn check_widget_origin(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let origin = match req.headers().get(header::ORIGIN) {
+        Some(origin) => origin.clone(),
+        None => return next.call(req).await
+    };
+    let origins = req
+        .app_data::<web::Data<WidgetOrigins>>()
+        .expect("The widget origins are not registered.")
+        .clone();
+    let origin_str = origin.to_str().unwrap_or_default();
+    if origin_str == origins.own_origin {
+        return next.call(req).await;
+    }
+    let site_id = match normalise_origin(origin_str).ok().and_then(|o| origins.sites.get(&o)) {
+        Some(site_id) => site_id.clone(),
+        None => {
+            tracing::warn!(origin = %origin_str, "Rejected a subscription request from an origin that is not allowed.");
+            let response = HttpResponse::Forbidden().body("This site is not allowed to embed the subscription form.");
+            let e = anyhow::anyhow!("The origin {} is not allowed", origin_str);
+            return Err(InternalError::from_response(e, response).into());
+        }
+    };
+
+    req.extensions_mut().insert(EmbeddingSite(site_id));
+    let mut response = next.call(req).await?;
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+    Ok(response)
+}
+
+/// CORS preflight for the widget's JSON post. Origins are checked by `check_widget_origin`.
+pub async fn subscriptions_preflight() -> HttpResponse {
+    HttpResponse::NoContent()
+        .insert_header((header::ACCESS_CONTROL_ALLOW_METHODS, "POST"))
+        .insert_header((header::ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, Accept"))
+        .insert_header((header::ACCESS_CONTROL_MAX_AGE, "86400"))
+        .finish()
+}
+
+/// Script for a third-party page: it renders a subscribe form where it is included
+/// and posts it to `/subscriptions` as JSON.
+pub async fn widget_script(base_url: web::Data<ApplicationBaseUrl>) -> HttpResponse {
+    let endpoint = serde_json::to_string(&format!("{}/subscriptions", base_url.0))
+        .expect("A string can always be serialised.");
+    HttpResponse::Ok()
+        .content_type("application/javascript; charset=utf-8")
+        .insert_header((header::CACHE_CONTROL, "public, max-age=3600"))
+        .body(include_str!("widget.js").replace("__SUBSCRIBE_ENDPOINT__", &endpoint))
+}
@@ -0,0 +1,92 @@
+(function () {
+    var endpoint = __SUBSCRIBE_ENDPOINT__;
+    var challengeEndpoint = endpoint + "/challenge";
+    var script = document.currentScript;
+    var consentTextVersion = script.dataset.consentTextVersion;
+
+    var form = document.createElement("form");
+    form.className = "newsletter-widget";
+    form.innerHTML =
+        '<label>Name <input type="text" name="name" required></label> ' +
+        '<label>Email <input type="email" name="email" required></label> ' +
+        // Kept out of sight and out of the tab order: only scripts fill it in.
+        '<div style="position:absolute;left:-10000px" aria-hidden="true">' +
+        '<label>Website <input type="text" name="website" tabindex="-1" autocomplete="off"></label>' +
+        '</div>' +
+        '<button type="submit">Subscribe</button>' +
+        '<p class="newsletter-widget-message" role="status"></p>';
+    script.parentNode.insertBefore(form, script);
+    var message = form.querySelector(".newsletter-widget-message");
+    var challenge = fetch(challengeEndpoint).then(function (response) {
+        return response.json();
+    });
+
+    function leadingZeroBits(bytes) {
+        var bits = 0;
+        for (var i = 0; i < bytes.length; i++) {
+            if (bytes[i] === 0) {
+                bits += 8;
+                continue;
+            }
+            return bits + Math.clz32(bytes[i]) - 24;
+        }
+        return bits;
+    }
+
+    // Finds a nonce such that SHA-256("<challenge>:<email>:<nonce>") starts with `difficulty` zero bits.
+    function solve(challenge, email, difficulty, nonce) {
+        nonce = nonce || 0;
+        if (difficulty === 0) {
+            return Promise.resolve("");
+        }
+        var data = new TextEncoder().encode(challenge + ":" + email + ":" + nonce);
+        return crypto.subtle.digest("SHA-256", data).then(function (hash) {
+            if (leadingZeroBits(new Uint8Array(hash)) >= difficulty) {
+                return String(nonce);
+            }
+            return solve(challenge, email, difficulty, nonce + 1);
+        });
+    }
+
+    form.addEventListener("submit", function (event) {
+        event.preventDefault();
+        var body = {
+            name: form.elements.name.value,
+            email: form.elements.email.value,
+            website: form.elements.website.value
+        };
+        if (consentTextVersion) {
+            body.consent_text_version = consentTextVersion;
+        }
+        challenge
+            .then(function (issued) {
+                return solve(issued.challenge, body.email, issued.difficulty).then(function (nonce) {
+                    body.challenge = issued.challenge;
+                    body.nonce = nonce;
+                    return fetch(endpoint, {
+                        method: "POST",
+                        headers: { "Content-Type": "application/json", "Accept": "application/json" },
+                        body: JSON.stringify(body)
+                    });
+                });
+            })
+            .then(function (response) {
+                return response.json().then(function (json) {
+                    return { ok: response.ok, json: json };
+                });
+            })
+            .then(function (result) {
+                if (result.ok) {
+                    form.reset();
+                    message.textContent = "Thank you! Please check your inbox to confirm your subscription.";
+                    return;
+                }
+                var fields = result.json.fields || {};
+                var errors = Object.keys(fields).map(function (field) { return fields[field]; });
+                message.textContent = errors.length > 0 ? errors.join(" ") : result.json.error;
+            })
+            .catch(function () {
+                message.textContent = "Something went wrong. Please try again later.";
+            });
+    });
+})();
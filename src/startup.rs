@@ -1,16 +1,20 @@
 use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::client_ip::TrustedProxies;
+use crate::configuration::{BotProtectionSettings, DatabaseSettings, RateLimitSettings, Settings, WidgetSettings};
+use crate::domain::EmailPolicy;
 use crate::email_client::EmailClient;
-use crate::routes::{confirm, health_check, home, login, login_form, log_out, subscribe, admin_dashboard, change_password, change_password_form, publish_newsletter, get_newsletter_form};
+use crate::mx_check::MxChecker;
+use crate::rate_limit::RateLimiter;
+use crate::routes::{subscribe_challenge, confirm, confirm_form, resend_confirmation, request_data_export, download_data_export, health_check, home, login, login_form, log_out, subscribe, unsubscribe, unsubscribe_form, unsubscribe_one_click, admin_dashboard, change_password, change_password_form, publish_newsletter, get_newsletter_form, count_newsletter_recipients, lists_form, create_list, tags_form, tag_subscriber, untag_subscriber, list_subscribers, export_subscribers, import_subscribers, import_subscribers_form, subscriber_details, force_confirm_subscriber, admin_unsubscribe_subscriber, admin_resend_confirmation, rename_subscriber, delete_subscriber, download_subscriber_data, send_subscriber_data_export, check_widget_origin, subscriptions_preflight, widget_script, WidgetOrigins, welcome_email_form, save_welcome_email};
 use actix_session::{SessionMiddleware, storage::RedisSessionStore};
 use actix_web::dev::Server;
 use actix_web::web::Data;
-use actix_web::{web, App, HttpServer, cookie::Key};
+use actix_web::{web, App, HttpServer, cookie::Key, http::Method};
 use actix_web_flash_messages::{FlashMessagesFramework, storage::CookieMessageStore};
 use actix_web_lab::middleware::from_fn;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::{PgPool, Pool, Postgres};
-use std::net::TcpListener;
+use std::net::{IpAddr, TcpListener};
 use tracing_actix_web::TracingLogger;
 use secrecy::{ExposeSecret, Secret};
 
@@ -24,6 +28,8 @@ impl Application {
         let connection_pool = get_connection_pool(&configuration.database);
         
         let email_client = configuration.email_client.client();
+        let email_policy = configuration.email_policy.policy()?;
+        let mx_checker = MxChecker::new(&configuration.mx_check)?;
 
         let address = format!(
             "{}:{}",
@@ -31,13 +37,21 @@ impl Application {
         );
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
+        let subscription_token_ttl = configuration.application.subscription_token_ttl();
         let server = run(
             listener,
             connection_pool,
             email_client,
+            email_policy,
+            mx_checker,
             configuration.application.base_url,
             configuration.application.hmac_secret,
-            configuration.redis_uri
+            subscription_token_ttl,
+            configuration.redis_uri,
+            configuration.rate_limit,
+            configuration.bot_protection,
+            configuration.widget,
+            configuration.application.trusted_proxies
         ).await?;
 
         Ok(Self { port, server })
@@ -54,29 +68,64 @@ impl Application {
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct SubscriptionTokenTtl(pub chrono::Duration);
+
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
+    email_policy: EmailPolicy,
+    mx_checker: MxChecker,
     base_url: String,
     hmac_secret: Secret<String>,
-    redis_uri: Secret<String>
+    subscription_token_ttl: chrono::Duration,
+    redis_uri: Secret<String>,
+    rate_limit: RateLimitSettings,
+    bot_protection: BotProtectionSettings,
+    widget: WidgetSettings,
+    trusted_proxies: Vec<IpAddr>
 ) -> Result<Server, anyhow::Error> {
     let db_pool = Data::new(db_pool);
     let email_client = Data::new(email_client);
+    let email_policy = Data::new(email_policy);
+    let mx_checker = Data::new(mx_checker);
+    let widget_origins = Data::new(WidgetOrigins::new(&base_url, widget.sites)?);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let subscription_token_ttl = Data::new(SubscriptionTokenTtl(subscription_token_ttl));
+    let bot_protection = Data::new(bot_protection);
+    let trusted_proxies = Data::new(TrustedProxies(trusted_proxies));
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let rate_limiter = Data::new(RateLimiter::new(&redis_uri, rate_limit).await?);
     let server = HttpServer::new(move || {
         App::new()
             .wrap(message_framework.clone())
             .wrap(SessionMiddleware::new(redis_store.clone(), secret_key.clone()))
             .wrap(TracingLogger::default())
             .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
-            .route("/subscriptions/confirm", web::get().to(confirm))
+            .service(
+                web::resource("/subscriptions")
+                .wrap(from_fn(check_widget_origin))
+                .route(web::post().to(subscribe))
+                .route(web::method(Method::OPTIONS).to(subscriptions_preflight))
+            )
+            .service(
+                web::resource("/subscriptions/challenge")
+                .wrap(from_fn(check_widget_origin))
+                .route(web::get().to(subscribe_challenge))
+            )
+            .route("/subscriptions/confirm", web::get().to(confirm_form))
+            .route("/subscriptions/confirm", web::post().to(confirm))
+            .route("/subscriptions/confirm/resend", web::post().to(resend_confirmation))
+            .route("/subscriptions/data-export", web::get().to(download_data_export))
+            .route("/subscriptions/data-export", web::post().to(request_data_export))
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe_form))
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/subscriptions/unsubscribe/one-click", web::post().to(unsubscribe_one_click))
+            .route("/widget.js", web::get().to(widget_script))
             .route("/", web::get().to(home))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
@@ -89,11 +138,43 @@ pub async fn run(
                 .route("/logout", web::post().to(log_out))
                 .route("/newsletters", web::post().to(publish_newsletter))
                 .route("/newsletters", web::get().to(get_newsletter_form))
+                .route("/newsletters/recipients", web::get().to(count_newsletter_recipients))
+                .route("/welcome-email", web::get().to(welcome_email_form))
+                .route("/welcome-email", web::post().to(save_welcome_email))
+                .route("/lists", web::get().to(lists_form))
+                .route("/lists", web::post().to(create_list))
+                .route("/tags", web::get().to(tags_form))
+                .route("/tags", web::post().to(tag_subscriber))
+                .route("/tags/remove", web::post().to(untag_subscriber))
+                .route("/subscribers", web::get().to(list_subscribers))
+                .route("/subscribers/export", web::get().to(export_subscribers))
+                .service(
+                    web::resource("/subscribers/import")
+                    // An audience export is well past the default 256kB payload limit.
+                    .app_data(web::PayloadConfig::new(16 * 1024 * 1024))
+                    .route(web::get().to(import_subscribers_form))
+                    .route(web::post().to(import_subscribers))
+                )
+                .route("/subscribers/{subscriber_id}", web::get().to(subscriber_details))
+                .route("/subscribers/{subscriber_id}/confirm", web::post().to(force_confirm_subscriber))
+                .route("/subscribers/{subscriber_id}/unsubscribe", web::post().to(admin_unsubscribe_subscriber))
+                .route("/subscribers/{subscriber_id}/resend-confirmation", web::post().to(admin_resend_confirmation))
+                .route("/subscribers/{subscriber_id}/name", web::post().to(rename_subscriber))
+                .route("/subscribers/{subscriber_id}/delete", web::post().to(delete_subscriber))
+                .route("/subscribers/{subscriber_id}/data-export", web::get().to(download_subscriber_data))
+                .route("/subscribers/{subscriber_id}/data-export", web::post().to(send_subscriber_data_export))
             )            
             // register the connection as part of the application state
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(email_policy.clone())
+            .app_data(mx_checker.clone())
             .app_data(base_url.clone())
+            .app_data(subscription_token_ttl.clone())
+            .app_data(widget_origins.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(bot_protection.clone())
+            .app_data(trusted_proxies.clone())
             .app_data(Data::new(HmacSecret(hmac_secret.clone())))
     })
     .listen(listener)?
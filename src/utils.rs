@@ -1,11 +1,17 @@
 use actix_web::{HttpResponse, http::header::LOCATION};
 
-pub fn e400<T: std::fmt::Debug + std::fmt::Display>(e: T) -> actix_web::Error
+pub fn e400<T>(e: T) -> actix_web::Error
     where T: std::fmt::Debug + std::fmt::Display + 'static
 {
     actix_web::error::ErrorBadRequest(e)
 }
 
+pub fn e404<T>(e: T) -> actix_web::Error
+    where T: std::fmt::Debug + std::fmt::Display + 'static
+{
+    actix_web::error::ErrorNotFound(e)
+}
+
 pub fn e500<T>(e: T) -> actix_web::Error
     where T: std::fmt::Debug + std::fmt::Display + 'static
 {
@@ -0,0 +1,163 @@
+use crate::domain::{SubscriberAttributes, SubscriberEmail, SubscriptionStatus, TemplateContext};
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::{delete_task, dequeue_task, render_newsletter_email, ExecutionOutcome, QueuedTask};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use sqlx::postgres::PgArguments;
+use sqlx::query::Query;
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+/// Sent once to every subscriber who confirms, when `enabled`. The bodies are
+/// templates, with the same placeholders as newsletter issues.
+pub struct WelcomeEmail {
+    pub enabled: bool,
+    pub subject: String,
+    pub html_content: String,
+    pub text_content: String
+}
+
+#[tracing::instrument(name = "Get the welcome email", skip(pool))]
+pub async fn get_welcome_email(pool: &PgPool) -> Result<WelcomeEmail, sqlx::Error> {
+    sqlx::query_as!(
+        WelcomeEmail,
+        r#"SELECT enabled, subject, html_content, text_content FROM welcome_email"#
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Does nothing while the welcome email is turned off.
+#[tracing::instrument(name = "Queue the welcome email", skip(transaction))]
+pub(crate) async fn enqueue_welcome_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO welcome_email_queue (subscriber_id)
+        SELECT $1 FROM welcome_email WHERE enabled
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(subscriber_id = tracing::field::Empty),
+    err
+)]
+pub async fn try_send_welcome_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl,
+    hmac_secret: &HmacSecret
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let (transaction, task) = match dequeue_task::<WelcomeEmailTask>(pool).await? {
+        Some(task) => task,
+        None => return Ok(ExecutionOutcome::EmptyQueue)
+    };
+    let subscriber_id = task.subscriber_id;
+    Span::current().record("subscriber_id", &display(subscriber_id));
+
+    let welcome_email = get_welcome_email(pool).await?;
+    match get_confirmed_subscriber(pool, subscriber_id).await? {
+        None => {
+            tracing::info!("Skipping a subscriber who is no longer confirmed.");
+        }
+        Some(_) if !welcome_email.enabled => {
+            tracing::info!("Skipping a subscriber: the welcome email was turned off after they confirmed.");
+        }
+        Some(subscriber) => match SubscriberEmail::from_stored(subscriber.email, subscriber.punycode_email) {
+            Ok(email) => {
+                let context = TemplateContext {
+                    name: &subscriber.name,
+                    email: email.as_ref(),
+                    attributes: &subscriber.attributes
+                };
+                let content = render_newsletter_email(
+                    &welcome_email.html_content,
+                    &welcome_email.text_content,
+                    &context,
+                    subscriber_id,
+                    base_url,
+                    hmac_secret
+                );
+                if let Err(e) = email_client
+                    .send_email_with_headers(
+                        &email,
+                        &welcome_email.subject,
+                        &content.html_content,
+                        &content.text_content,
+                        &content.headers
+                    )
+                    .await
+                {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to send the welcome email to a confirmed subscriber. Skipping."
+                    );
+                }
+            }
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Skipping a confirmed subscriber. Their stored contact details are invalid"
+                );
+            }
+        }
+    }
+
+    delete_task(transaction, &task).await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[derive(sqlx::FromRow)]
+struct WelcomeEmailTask {
+    subscriber_id: Uuid
+}
+impl QueuedTask for WelcomeEmailTask {
+    const QUEUE: &'static str = "welcome_email_queue";
+    const KEY: &'static [&'static str] = &["subscriber_id"];
+
+    fn bind_key<'q>(&'q self, query: Query<'q, Postgres, PgArguments>) -> Query<'q, Postgres, PgArguments> {
+        query.bind(self.subscriber_id)
+    }
+}
+
+struct ConfirmedSubscriber {
+    email: String,
+    punycode_email: Option<String>,
+    name: String,
+    attributes: SubscriberAttributes
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid
+) -> Result<Option<ConfirmedSubscriber>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT email, punycode_email, name, attributes
+        FROM subscriptions
+        WHERE id = $1 AND status = $2
+        "#,
+        subscriber_id,
+        SubscriptionStatus::Confirmed as SubscriptionStatus
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(r.map(|r| ConfirmedSubscriber {
+        email: r.email,
+        punycode_email: r.punycode_email,
+        name: r.name,
+        attributes: SubscriberAttributes::from_json(r.attributes)
+    }))
+}
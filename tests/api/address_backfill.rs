@@ -0,0 +1,129 @@
+use crate::helpers::{spawn_app, TestApp};
+use rust2prod::address_backfill::backfill_subscriber_addresses;
+use rust2prod::domain::{EmailPolicy, SubscriptionStatus};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn email_policy() -> EmailPolicy {
+    EmailPolicy::new(true, Vec::new())
+}
+
+/// A subscriber as stored before the application recorded the derived forms of an address.
+async fn insert_legacy_subscriber(app: &TestApp, email: &str, minutes_ago: i32) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, status, subscribed_at)
+        VALUES ($1, $2, 'A subscriber', $3, now() - make_interval(mins => $4))
+        "#,
+        Uuid::new_v4(),
+        email,
+        SubscriptionStatus::Confirmed as SubscriptionStatus,
+        minutes_ago
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn the_backfill_stores_the_punycode_form_of_addresses_without_one() {
+    // arrange
+    let app = spawn_app().await;
+    insert_legacy_subscriber(&app, "josé@bücher.de", 0).await;
+    insert_legacy_subscriber(&app, "ursula@example.com", 0).await;
+
+    // act
+    backfill_subscriber_addresses(&app.db_pool, &email_policy()).await.unwrap();
+
+    // assert
+    let saved = sqlx::query!("SELECT email, punycode_email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved[0].email, "josé@bücher.de");
+    assert_eq!(saved[0].punycode_email.as_deref(), Some("josé@xn--bcher-kva.de"));
+    assert_eq!(saved[1].punycode_email.as_deref(), Some("ursula@example.com"));
+}
+
+#[tokio::test]
+async fn the_backfill_leaves_addresses_it_cannot_parse_alone() {
+    // arrange
+    let app = spawn_app().await;
+    insert_legacy_subscriber(&app, "not an address", 0).await;
+
+    // act
+    let outcome = backfill_subscriber_addresses(&app.db_pool, &email_policy()).await;
+
+    // assert
+    assert!(outcome.is_ok());
+    let saved = sqlx::query!("SELECT punycode_email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.punycode_email, None);
+}
+
+#[tokio::test]
+async fn the_backfill_stores_the_canonical_form_of_addresses_without_one() {
+    // arrange
+    let app = spawn_app().await;
+    insert_legacy_subscriber(&app, "Ursula.Le.Guin+news@gmail.com", 0).await;
+    insert_legacy_subscriber(&app, "josé@bücher.de", 0).await;
+
+    // act
+    backfill_subscriber_addresses(&app.db_pool, &email_policy()).await.unwrap();
+
+    // assert
+    let saved = sqlx::query!("SELECT canonical_email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved[0].canonical_email.as_deref(), Some("ursulaleguin@gmail.com"));
+    assert_eq!(saved[1].canonical_email.as_deref(), Some("josé@xn--bcher-kva.de"));
+}
+
+#[tokio::test]
+async fn the_oldest_of_several_spellings_of_a_mailbox_keeps_the_canonical_address() {
+    // arrange
+    let app = spawn_app().await;
+    insert_legacy_subscriber(&app, "ursulaleguin+later@gmail.com", 10).await;
+    insert_legacy_subscriber(&app, "ursula.le.guin@gmail.com", 20).await;
+
+    // act
+    backfill_subscriber_addresses(&app.db_pool, &email_policy()).await.unwrap();
+
+    // assert
+    let saved = sqlx::query!("SELECT email, canonical_email FROM subscriptions ORDER BY subscribed_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved[0].email, "ursula.le.guin@gmail.com");
+    assert_eq!(saved[0].canonical_email.as_deref(), Some("ursulaleguin@gmail.com"));
+    assert_eq!(saved[1].canonical_email, None);
+}
+
+#[tokio::test]
+async fn a_backfilled_subscriber_is_recognised_under_another_spelling() {
+    // arrange
+    let app = spawn_app().await;
+    insert_legacy_subscriber(&app, "ursula.le.guin@gmail.com", 0).await;
+    backfill_subscriber_addresses(&app.db_pool, &email_policy()).await.unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app.post_subscriptions("name=le%20guin&email=UrsulaLeGuin%2Bnews%40gmail.com".into()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "ursula.le.guin@gmail.com");
+}
@@ -9,7 +9,7 @@ async fn health_check_works() {
 
     // act
     let response = client
-        .get(&format!("{}/health_check", address))
+        .get(format!("{}/health_check", address))
         .send()
         .await
         .expect("Failed to execute request.");
@@ -1,14 +1,18 @@
-use rust2prod::configuration::{get_configuration, DatabaseSettings};
+use rust2prod::configuration::{get_configuration, DatabaseSettings, RateLimit, Settings, WidgetSite};
 use rust2prod::email_client::EmailClient;
 use rust2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
-use rust2prod::startup::{get_connection_pool, Application};
+use rust2prod::domain::UnsubscribeToken;
+use rust2prod::startup::{get_connection_pool, Application, ApplicationBaseUrl, HmacSecret};
 use rust2prod::telemetry::{get_subscriber, init_subscriber};
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 use argon2::password_hash::SaltString;
 use once_cell::sync::Lazy;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
@@ -66,6 +70,8 @@ pub struct TestApp {
     pub port: u16,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub hmac_secret: HmacSecret,
+    pub default_list_id: Uuid,
     pub(crate) test_user: TestUser
 }
 
@@ -77,7 +83,13 @@ pub struct ConfirmationLinks {
 impl TestApp {
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue = try_execute_task(&self.db_pool, &self.email_client)
+            let base_url = ApplicationBaseUrl(self.address.clone());
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &base_url,
+                &self.hmac_secret
+            )
                 .await
                 .unwrap() 
             {
@@ -88,7 +100,7 @@ impl TestApp {
 
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -96,13 +108,136 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriptions_json_from(
+        &self,
+        origin: &str,
+        body: &serde_json::Value
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Origin", origin)
+            .header("Accept", "application/json")
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn preflight_subscriptions(&self, origin: &str) -> reqwest::Response {
+        self.api_client
+            .request(reqwest::Method::OPTIONS, format!("{}/subscriptions", &self.address))
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "content-type")
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribe_challenge(&self) -> serde_json::Value {
+        self.api_client
+            .get(format!("{}/subscriptions/challenge", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_widget_script(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/widget.js", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn confirm(&self, subscription_token: String) -> reqwest::Response {
         self.api_client
-            .get(
-                &format!("{}/subscriptions/confirm?subscription_token={}",
-                         &self.address,
-                         subscription_token
-                ))
+            .post(format!("{}/subscriptions/confirm", &self.address))
+            .form(&[("subscription_token", subscription_token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Submit the form behind a confirmation link, as a subscriber would.
+    pub async fn confirm_with_link(&self, confirmation_link: &reqwest::Url) -> reqwest::Response {
+        let subscription_token = confirmation_link
+            .query_pairs()
+            .find(|(k, _)| k == "subscription_token")
+            .expect("The confirmation link has no subscription token.")
+            .1
+            .into_owned();
+        self.confirm(subscription_token).await
+    }
+
+    pub fn unsubscribe_token(&self, subscriber_id: Uuid) -> String {
+        UnsubscribeToken::generate(subscriber_id, &self.hmac_secret.0)
+            .as_ref()
+            .to_owned()
+    }
+
+    pub async fn get_unsubscribe(&self, subscriber_id: Uuid, token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscriptions/unsubscribe", &self.address))
+            .query(&[("subscriber_id", subscriber_id.to_string().as_str()), ("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_unsubscribe(&self, subscriber_id: Uuid, token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/unsubscribe", &self.address))
+            .form(&[("subscriber_id", subscriber_id.to_string().as_str()), ("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_one_click_unsubscribe(&self, subscriber_id: Uuid, token: &str) -> reqwest::Response {
+        // Mail providers fire this request without any cookies.
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/unsubscribe/one-click", &self.address))
+            .query(&[("subscriber_id", subscriber_id.to_string().as_str()), ("token", token)])
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("List-Unsubscribe=One-Click")
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_data_export_request(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/data-export", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_data_export(&self, link: &reqwest::Url) -> reqwest::Response {
+        self.api_client
+            .get(link.clone())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscriber_data(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers/{}/data-export", &self.address, subscriber_id))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -131,8 +266,8 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
 
         ConfirmationLinks {
             html,
@@ -144,7 +279,7 @@ impl TestApp {
         where Body: serde::Serialize
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
             .form(body)
             .send()
             .await
@@ -155,7 +290,7 @@ impl TestApp {
         where Body: serde::Serialize
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -171,7 +306,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -182,7 +317,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")                        
@@ -194,7 +329,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -211,7 +346,7 @@ impl TestApp {
     pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response 
         where Body: serde::Serialize {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
@@ -220,15 +355,182 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_lists<Body>(&self, body: &Body) -> reqwest::Response
+        where Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/lists", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_tags<Body>(&self, body: &Body) -> reqwest::Response
+        where Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/tags", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_tags_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/tags", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_newsletter_recipients(&self, segment: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/newsletters/recipients?{}", &self.address, segment))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_export(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers/export?{}", &self.address, query))
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn get_subscriber_html(&self, subscriber_id: Uuid) -> String {
+        self.api_client
+            .get(format!("{}/admin/subscribers/{}", &self.address, subscriber_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_subscriber_action<Body>(
+        &self,
+        subscriber_id: Uuid,
+        action: &str,
+        body: &Body
+    ) -> reqwest::Response
+        where Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/subscribers/{}/{}", &self.address, subscriber_id, action))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_import_subscribers(&self, csv: &str, mode: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .form(&[
+                ("csv", csv.to_string()),
+                ("mode", mode.to_string()),
+                ("list_id", self.default_list_id.to_string())
+            ])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn create_list(&self, name: &str) -> Uuid {
+        let list_id = Uuid::new_v4();
+        sqlx::query!(
+            "INSERT INTO lists (list_id, name) VALUES ($1, $2)",
+            list_id,
+            name
+        )
+        .execute(&self.db_pool)
+        .await
+        .expect("Failed to create list.");
+        list_id
+    }
+
+    pub async fn create_confirmed_subscriber(&self, email: &str, list_id: Uuid) {
+        let body = format!(
+            "name=le%20guin&email={}&list_id={}",
+            urlencoding::encode(email),
+            list_id
+        );
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount_as_scoped(&self.email_server)
+            .await;
+        self.post_subscriptions(body)
+            .await
+            .error_for_status()
+            .unwrap();
+        let email_request = self.email_server.received_requests().await.unwrap().pop().unwrap();
+        let confirmation_links = self.get_confirmation_links(&email_request);
+        self.confirm_with_link(&confirmation_links.html)
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
     pub async fn get_newsletters_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/admin/newsletters", self.address))
+            .get(format!("{}/admin/newsletters", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_welcome_email<Body>(&self, body: &Body) -> reqwest::Response
+        where Body: serde::Serialize
+    {
+        self.api_client
+            .post(format!("{}/admin/welcome-email", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_welcome_email_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/welcome-email", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -239,6 +541,11 @@ impl TestApp {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// For tests that need settings other than the defaults, which `configure` can change.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -248,6 +555,15 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        // Every test app shares the same Redis, so each gets its own counters.
+        c.rate_limit.key_prefix = Uuid::new_v4().to_string();
+        c.rate_limit.per_ip = RateLimit { max_requests: 20, window_seconds: 3600 };
+        c.rate_limit.per_email = RateLimit { max_requests: 3, window_seconds: 3600 };
+        c.widget.sites = vec![WidgetSite {
+            id: "blog".into(),
+            origin: "https://blog.example.com".into()
+        }];
+        configure(&mut c);
         c
     };
 
@@ -259,20 +575,29 @@ pub async fn spawn_app() -> TestApp {
 
     let port = application.port();
     let address = format!("http://127.0.0.1:{}", port);
-    let _ = tokio::spawn(application.run_until_stopped());
+    tokio::spawn(application.run_until_stopped());
     let api_client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
         .cookie_store(true)
         .build()
         .unwrap();
 
+    let db_pool = get_connection_pool(&configuration.database);
+    let default_list_id = sqlx::query!("SELECT list_id FROM lists WHERE is_default")
+        .fetch_one(&db_pool)
+        .await
+        .expect("Failed to fetch the default list.")
+        .list_id;
+
     let test_app = TestApp {
         address,
-        db_pool: get_connection_pool(&configuration.database),
+        db_pool,
         email_server,
         port,
         api_client,
         email_client: configuration.email_client.client(),
+        hmac_secret: HmacSecret(configuration.application.hmac_secret.clone()),
+        default_list_id,
         test_user: TestUser::generate()
     };
 
@@ -307,3 +632,76 @@ pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
 }
+
+/// A DNS resolver standing in for the real one in the MX check: `example.com` has an
+/// MX record, `a-record-only.example` has an A record only, `unresponsive.example`
+/// never gets an answer and every other domain does not exist.
+pub struct FakeResolver {
+    pub address: String,
+    pub queries: Arc<AtomicUsize>
+}
+
+impl FakeResolver {
+    pub async fn start() -> Self {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counter = queries.clone();
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            while let Ok((n, peer)) = socket.recv_from(&mut buffer).await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                if let Some(response) = fake_dns_response(&buffer[..n]) {
+                    let _ = socket.send_to(&response, peer).await;
+                }
+            }
+        });
+        Self { address, queries }
+    }
+
+    pub fn query_count(&self) -> usize {
+        self.queries.load(Ordering::SeqCst)
+    }
+}
+
+fn fake_dns_response(query: &[u8]) -> Option<Vec<u8>> {
+    let mut labels = Vec::new();
+    let mut at = 12;
+    while query[at] != 0 {
+        let length = query[at] as usize;
+        labels.push(String::from_utf8_lossy(&query[at + 1..at + 1 + length]).into_owned());
+        at += 1 + length;
+    }
+    let question_end = at + 5;
+    let record_type = u16::from_be_bytes([query[at + 1], query[at + 2]]);
+    let domain_record_type = match labels.join(".").as_str() {
+        "example.com" => Some(15),
+        "a-record-only.example" => Some(1),
+        // Too many MX records for a datagram: the answer comes back cut off and flagged.
+        "truncated.example" if record_type == 15 => {
+            let mut response = query[..question_end].to_vec();
+            response[2] |= 0x82;
+            response[3] = 0x80;
+            return Some(response);
+        },
+        "truncated.example" => Some(15),
+        "unresponsive.example" => return None,
+        _ => None
+    };
+
+    let mut response = query[..question_end].to_vec();
+    response[2] |= 0x80;
+    match domain_record_type {
+        // NXDOMAIN
+        None => response[3] = 0x83,
+        Some(domain_record_type) if domain_record_type == record_type => {
+            response[3] = 0x80;
+            response[7] = 1;
+            response.extend_from_slice(&[0xc0, 12]);
+            response.extend_from_slice(&record_type.to_be_bytes());
+            response.extend_from_slice(&[0, 1, 0, 0, 0x0e, 0x10, 0, 4, 127, 0, 0, 1]);
+        },
+        Some(_) => response[3] = 0x80
+    }
+    Some(response)
+}
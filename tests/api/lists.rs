@@ -0,0 +1,202 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use rust2prod::domain::SubscriptionStatus;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_lists() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.post_lists(&serde_json::json!({ "name": "Weekly digest" })).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_can_create_a_list() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.post_lists(&serde_json::json!({ "name": "Weekly digest" })).await;
+    let html_page = app.get_lists_html().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/lists");
+    assert!(html_page.contains("<p><i>The list has been created.</i></p>"));
+    assert!(html_page.contains("Weekly digest"));
+}
+
+#[tokio::test]
+async fn subscribing_without_a_list_joins_the_default_list() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+
+    // assert
+    let membership = sqlx::query!(r#"SELECT list_id, status AS "status: SubscriptionStatus" FROM list_memberships"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved membership.");
+    assert_eq!(membership.list_id, app.default_list_id);
+    assert_eq!(membership.status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn subscribing_to_several_lists_creates_one_membership_per_list() {
+    // arrange
+    let app = spawn_app().await;
+    let first_list = app.create_list("Engineering").await;
+    let second_list = app.create_list("Product").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&list_id={}&list_id={}",
+        first_list,
+        second_list
+    );
+
+    // act
+    let response = app.post_subscriptions(body).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let mut list_ids: Vec<Uuid> = sqlx::query!("SELECT list_id FROM list_memberships")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved memberships.")
+        .into_iter()
+        .map(|r| r.list_id)
+        .collect();
+    list_ids.sort();
+    let mut expected = vec![first_list, second_list];
+    expected.sort();
+    assert_eq!(list_ids, expected);
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected_with_a_400() {
+    // arrange
+    let app = spawn_app().await;
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&list_id={}",
+        Uuid::new_v4()
+    );
+
+    // act
+    let response = app.post_subscriptions(body).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_confirmed_subscriber_joining_a_new_list_gets_a_confirmation_email() {
+    // arrange
+    let app = spawn_app().await;
+    let new_list = app.create_list("Engineering").await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com", app.default_list_id).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = format!("name=le%20guin&email=ursula_le_guin%40gmail.com&list_id={}", new_list);
+
+    // act
+    let response = app.post_subscriptions(body).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let membership = sqlx::query!(
+        r#"SELECT status AS "status: SubscriptionStatus" FROM list_memberships WHERE list_id = $1"#,
+        new_list
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved membership.");
+    assert_eq!(membership.status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn newsletters_are_only_delivered_to_members_of_the_chosen_lists() {
+    // arrange
+    let app = spawn_app().await;
+    let engineering = app.create_list("Engineering").await;
+    let product = app.create_list("Product").await;
+    app.create_confirmed_subscriber("engineer@example.com", engineering).await;
+    app.create_confirmed_subscriber("product@example.com", product).await;
+    app.post_login_with_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "list_id": engineering
+    });
+
+    // act
+    let response = app.post_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "engineer@example.com");
+}
+
+#[tokio::test]
+async fn publishing_without_choosing_a_list_is_rejected() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com", app.default_list_id).await;
+    app.post_login_with_test_user().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    });
+
+    // act
+    let response = app.post_newsletter(&newsletter_request_body).await;
+    let html_page = app.get_newsletters_html().await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert!(html_page.contains(
+        "<p><i>You must choose at least one list to send the issue to.</i></p>"
+    ));
+}
@@ -1,8 +1,19 @@
+mod address_backfill;
 mod health_check;
 mod helpers;
+mod lists;
 mod login;
 mod newsletter;
+mod subscriber_actions;
+mod subscribers;
+mod subscribers_export;
+mod subscribers_import;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_data_export;
+mod subscriptions_unsubscribe;
+mod tags;
+mod widget;
 mod admin_dashboard;
 mod change_password;
+mod welcome_email;
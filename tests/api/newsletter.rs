@@ -23,7 +23,8 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
        "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
         "html_content": "<p>Newsletter body as HTML</p>",
-        "idempotency_key": uuid::Uuid::new_v4().to_string()
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
     });
 
     // act
@@ -46,11 +47,7 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     let app = spawn_app().await;
     app.post_login_with_test_user().await;
     let confirmation_links = create_unconfirmed_subscriber(&app).await;
-    let confirmation_response = reqwest::Client::new()
-        .get(confirmation_links.html)
-        .send()
-        .await
-        .unwrap();
+    let confirmation_response = app.confirm_with_link(&confirmation_links.html).await;
     assert_eq!(confirmation_response.status().as_u16(), 200);
 
     Mock::given(path("/email"))
@@ -64,7 +61,8 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
         "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
         "html_content": "<p>Newsletter body as HTML</p>",
-        "idempotency_key": uuid::Uuid::new_v4().to_string()
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
     });
 
     // act
@@ -118,7 +116,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     let name: String = Name().fake();
     let email: String = SafeEmail().fake();
     
-    let body = serde_urlencoded::to_string(&serde_json::json!({
+    let body = serde_urlencoded::to_string(serde_json::json!({
         "name": name,
         "email": email
     })).unwrap();
@@ -131,7 +129,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .mount_as_scoped(&app.email_server)
         .await;
 
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
@@ -143,13 +141,12 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .pop()
         .unwrap();
 
-    app.get_confirmation_links(&email_request)
+    app.get_confirmation_links(email_request)
 }
 
 async fn create_confirmed_subscriber(app: &TestApp) {
     let confirmation_links = create_unconfirmed_subscriber(app).await;
-    let client = reqwest::ClientBuilder::default().build().expect("Unable to create client.");
-    client.get(confirmation_links.html).send().await.expect("Failed to make request.");
+    app.confirm_with_link(&confirmation_links.html).await;
 }
 
 #[tokio::test]
@@ -170,7 +167,8 @@ async fn newsletter_creation_is_idempotent() {
         "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
         "html_content": "<p>Newsletter body as HTML</p>",
-        "idempotency_key": uuid::Uuid::new_v4().to_string()
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
     });
 
     // act
@@ -206,7 +204,8 @@ async fn concurrent_form_submission_is_handled_gracefully() {
         "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
         "html_content": "<p>Newsletter body as HTML</p>",
-        "idempotency_key": uuid::Uuid::new_v4().to_string()
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
     });
 
     // act
@@ -232,7 +231,8 @@ async fn transient_errors_do_not_cause_duplicate_deliveries_on_retries() {
         "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
         "html_content": "<p>Newsletter body as HTML</p>",
-        "idempotency_key": uuid::Uuid::new_v4().to_string()
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
     });
     create_confirmed_subscriber(&app).await;
     create_confirmed_subscriber(&app).await;
@@ -269,4 +269,281 @@ async fn transient_errors_do_not_cause_duplicate_deliveries_on_retries() {
     // assert
     assert_eq!(response1.status().as_u16(), 500);
     assert_eq!(response2.status().as_u16(), 303);
-}
\ No newline at end of file
+}
+
+async fn unsubscribe_all_subscribers(app: &TestApp) {
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    for subscriber in subscribers {
+        let token = app.unsubscribe_token(subscriber.id);
+        app.post_unsubscribe(subscriber.id, &token)
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unsubscribed_subscribers() {
+    // arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    unsubscribe_all_subscribers(&app).await;
+    app.post_login_with_test_user().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
+    });
+
+    // act
+    let response = app.post_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn subscribers_who_unsubscribe_after_publishing_do_not_receive_the_issue() {
+    // arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
+    });
+
+    // act
+    app.post_newsletter(&newsletter_request_body).await;
+    unsubscribe_all_subscribers(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn newsletter_emails_contain_an_unsubscribe_link() {
+    // arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
+    });
+
+    // act
+    app.post_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    let unsubscribe_link = format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&token={}",
+        app.address,
+        subscriber.id,
+        app.unsubscribe_token(subscriber.id)
+    );
+    assert!(body["TextBody"].as_str().unwrap().contains(&unsubscribe_link));
+    assert!(body["HtmlBody"].as_str().unwrap().contains(&unsubscribe_link));
+}
+
+#[tokio::test]
+async fn newsletter_emails_carry_one_click_unsubscribe_headers() {
+    // arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
+    });
+
+    // act
+    app.post_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    let one_click_link = format!(
+        "{}/subscriptions/unsubscribe/one-click?subscriber_id={}&token={}",
+        app.address,
+        subscriber.id,
+        app.unsubscribe_token(subscriber.id)
+    );
+    assert_eq!(
+        body["Headers"],
+        serde_json::json!([
+            { "Name": "List-Unsubscribe", "Value": format!("<{}>", one_click_link) },
+            { "Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click" }
+        ])
+    );
+}
+
+#[tokio::test]
+async fn newsletter_content_is_personalised_for_each_recipient() {
+    // arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber = sqlx::query!(
+        r#"UPDATE subscriptions SET attributes = '{"company": "Black & Decker"}' RETURNING name"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    app.post_login_with_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": r#"Hi {{ name }} from {{ attributes.company }}, {{ attributes.role | default("reader") }}!"#,
+        "html_content": r#"<p>Hi {{ name }} from {{ attributes.company }}, {{ attributes.role | default("reader") }}!</p>"#,
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
+    });
+
+    // act
+    app.post_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["TextBody"].as_str().unwrap().starts_with(
+        &format!("Hi {} from Black & Decker, reader!", subscriber.name)
+    ));
+    assert!(body["HtmlBody"].as_str().unwrap().starts_with(
+        &format!("<p>Hi {} from Black &amp; Decker, reader!</p>", subscriber.name)
+    ));
+}
+
+#[tokio::test]
+async fn issues_are_delivered_to_the_stored_punycode_form_of_the_address() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("josé@bücher.de", app.default_list_id).await;
+    let saved = sqlx::query!("SELECT punycode_email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.punycode_email.as_deref(), Some("josé@xn--bcher-kva.de"));
+    // Not something the worker could derive from the address: it must read it.
+    sqlx::query!("UPDATE subscriptions SET punycode_email = 'jose@relay.example.com'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_login_with_test_user().await;
+    when_sending_an_email()
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
+    });
+
+    // act
+    app.post_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "jose@relay.example.com");
+}
+
+#[tokio::test]
+async fn issues_with_an_invalid_template_are_rejected_before_publishing() {
+    // arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hi {{ nme }}!",
+        "html_content": "<p>Hi {{ name }}!</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
+    });
+
+    // act
+    let response = app.post_newsletter(&newsletter_request_body).await;
+    let html_page = app.get_newsletters_html().await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert!(html_page.contains("The plain text content is not a valid template"));
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
@@ -0,0 +1,350 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use rust2prod::domain::SubscriptionStatus;
+
+async fn create_pending_subscriber(app: &TestApp) -> (Uuid, ConfirmationLinks) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    (subscriber.id, app.get_confirmation_links(&email_request))
+}
+
+async fn subscriber_status(app: &TestApp, subscriber_id: Uuid) -> (SubscriptionStatus, Vec<SubscriptionStatus>) {
+    let subscriber = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    let memberships = sqlx::query!(
+        r#"SELECT status AS "status: SubscriptionStatus" FROM list_memberships WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved memberships.");
+    (
+        subscriber.status,
+        memberships.into_iter().map(|m| m.status).collect()
+    )
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_a_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, _) = create_pending_subscriber(&app).await;
+
+    // act
+    let response = app.post_subscriber_action(subscriber_id, "delete", &()).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+    let (status, _) = subscriber_status(&app, subscriber_id).await;
+    assert_eq!(status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn admins_can_confirm_a_subscriber_without_an_email() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, confirmation_links) = create_pending_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.post_subscriber_action(subscriber_id, "confirm", &()).await;
+    let html_page = app.get_subscriber_html(subscriber_id).await;
+
+    // assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert!(html_page.contains("<p><i>The subscriber has been confirmed.</i></p>"));
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await,
+        (SubscriptionStatus::Confirmed, vec![SubscriptionStatus::Confirmed])
+    );
+    let confirmation_response = app.confirm_with_link(&confirmation_links.html).await;
+    assert_eq!(confirmation_response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn admins_can_unsubscribe_a_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, _) = create_pending_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.post_subscriber_action(subscriber_id, "unsubscribe", &()).await;
+    let html_page = app.get_subscriber_html(subscriber_id).await;
+
+    // assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert!(html_page.contains("<p><i>The subscriber has been unsubscribed.</i></p>"));
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await,
+        (SubscriptionStatus::Unsubscribed, vec![SubscriptionStatus::Unsubscribed])
+    );
+}
+
+#[tokio::test]
+async fn admins_cannot_confirm_a_subscriber_who_unsubscribed() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, _) = create_pending_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+    app.post_subscriber_action(subscriber_id, "unsubscribe", &()).await;
+
+    // act
+    let response = app.post_subscriber_action(subscriber_id, "confirm", &()).await;
+    let html_page = app.get_subscriber_html(subscriber_id).await;
+
+    // assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert!(html_page.contains("<p><i>Only subscribers awaiting confirmation can be confirmed.</i></p>"));
+    assert!(html_page.contains("<td>pending_confirmation</td><td>unsubscribed</td><td>admin</td>"));
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await,
+        (SubscriptionStatus::Unsubscribed, vec![SubscriptionStatus::Unsubscribed])
+    );
+}
+
+#[tokio::test]
+async fn admins_can_only_confirm_subscribers_awaiting_confirmation() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, _) = create_pending_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+
+    for status in [SubscriptionStatus::Confirmed, SubscriptionStatus::Bounced, SubscriptionStatus::Complained] {
+        sqlx::query!(
+            "UPDATE subscriptions SET status = $1 WHERE id = $2",
+            status as SubscriptionStatus,
+            subscriber_id
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+        // act
+        let response = app.post_subscriber_action(subscriber_id, "confirm", &()).await;
+        let html_page = app.get_subscriber_html(subscriber_id).await;
+
+        // assert
+        assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+        assert!(
+            html_page.contains("<p><i>Only subscribers awaiting confirmation can be confirmed.</i></p>"),
+            "Confirmed a subscriber who was {}.",
+            status
+        );
+        assert_eq!(subscriber_status(&app, subscriber_id).await.0, status);
+    }
+}
+
+#[tokio::test]
+async fn admins_can_resend_a_working_confirmation_email() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, old_confirmation_links) = create_pending_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app.post_subscriber_action(subscriber_id, "resend-confirmation", &()).await;
+
+    // assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert!(app.get_subscriber_html(subscriber_id).await
+        .contains("<p><i>A new confirmation email has been sent.</i></p>"));
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    assert_eq!(app.confirm_with_link(&old_confirmation_links.html).await.status().as_u16(), 401);
+    assert_eq!(app.confirm_with_link(&confirmation_links.html).await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_not_resent_to_confirmed_subscribers() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com", app.default_list_id).await;
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_login_with_test_user().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.post_subscriber_action(subscriber.id, "resend-confirmation", &()).await;
+
+    // assert
+    assert!(app.get_subscriber_html(subscriber.id).await.contains(
+        "<p><i>Only subscribers awaiting confirmation can be sent a confirmation email.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn admins_can_rename_a_subscriber_with_a_valid_name() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, _) = create_pending_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+
+    // act - part 1 - invalid name
+    app.post_subscriber_action(subscriber_id, "name", &serde_json::json!({ "name": "<script>" })).await;
+    let html_page = app.get_subscriber_html(subscriber_id).await;
+    assert!(html_page.contains("<p><i>&lt;script&gt; is not a valid subscriber name.</i></p>"));
+
+    // act - part 2 - valid name
+    let response = app.post_subscriber_action(
+        subscriber_id,
+        "name",
+        &serde_json::json!({ "name": "Ursula K. Le Guin" })
+    ).await;
+
+    // assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+}
+
+#[tokio::test]
+async fn admins_can_delete_a_subscriber_and_everything_referring_to_them() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, _) = create_pending_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+    app.post_tags(&serde_json::json!({ "email": "ursula_le_guin@gmail.com", "tag": "beta" })).await;
+
+    // act
+    let response = app.post_subscriber_action(subscriber_id, "delete", &()).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers("").await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The subscriber has been deleted.</i></p>"));
+    let counts = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM subscriptions) as "subscriptions!",
+            (SELECT COUNT(*) FROM subscription_tokens) as "tokens!",
+            (SELECT COUNT(*) FROM list_memberships) as "memberships!",
+            (SELECT COUNT(*) FROM subscriber_tags) as "tags!"
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!((counts.subscriptions, counts.tokens, counts.memberships, counts.tags), (0, 0, 0, 0));
+}
+
+#[tokio::test]
+async fn the_subscriber_page_shows_the_consent_record() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, _) = create_pending_subscriber(&app).await;
+    app.post_login_with_test_user().await;
+
+    // act
+    app.post_subscriber_action(subscriber_id, "confirm", &()).await;
+    let html_page = app.get_subscriber_html(subscriber_id).await;
+
+    // assert
+    assert!(html_page.contains("<td>subscribed</td><td>subscription_form</td><td>127.0.0.1</td>"));
+    assert!(html_page.contains("<td>confirmed</td><td>admin</td><td>127.0.0.1</td>"));
+}
+
+#[tokio::test]
+async fn erasing_a_subscriber_removes_their_deliveries_and_keeps_only_a_tombstone() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, _) = create_pending_subscriber(&app).await;
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at)
+        VALUES ($1, 'Issue #1', 'text', '<p>html</p>', now()::text)
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email) VALUES ($1, 'ursula_le_guin@gmail.com')",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_id) VALUES ($1, $2)",
+        issue_id,
+        subscriber_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.post_subscriber_action(subscriber_id, "delete", &()).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let counts = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM issue_delivery_queue) as "queue!",
+            (SELECT COUNT(*) FROM issue_deliveries) as "deliveries!"
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!((counts.queue, counts.deliveries), (0, 0));
+    let tombstones = sqlx::query!("SELECT email_hash FROM erasure_tombstones")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tombstones.len(), 1);
+    assert!(!tombstones[0].email_hash.contains("ursula"));
+}
+
+#[tokio::test]
+async fn actions_on_an_unknown_subscriber_are_reported() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.post_subscriber_action(Uuid::new_v4(), "confirm", &()).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers("").await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The subscriber does not exist.</i></p>"));
+}
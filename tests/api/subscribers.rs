@@ -0,0 +1,146 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use std::collections::HashSet;
+use uuid::Uuid;
+use rust2prod::domain::SubscriptionStatus;
+
+async fn insert_subscriber(app: &TestApp, email: &str, name: &str, status: SubscriptionStatus, minutes_ago: i32) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, status, subscribed_at)
+        VALUES ($1, $2, $3, $4, '2026-01-01T00:00:00Z'::timestamptz - make_interval(mins => $5))
+        "#,
+        Uuid::new_v4(),
+        email,
+        name,
+        status as SubscriptionStatus,
+        minutes_ago
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert subscriber.");
+}
+
+async fn get_subscribers_html(app: &TestApp, query: &str) -> String {
+    let response = app.get_subscribers(query).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.text().await.unwrap()
+}
+
+fn next_page_query(html_page: &str) -> Option<String> {
+    let end = html_page.find(r#"">Next page</a>"#)?;
+    let start = html_page[..end].rfind("/admin/subscribers?")? + "/admin/subscribers?".len();
+    Some(html_page[start..end].replace("&amp;", "&"))
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_browse_subscribers() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.get_subscribers("").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_with_their_status_most_recent_first() {
+    // arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula Le Guin", SubscriptionStatus::Confirmed, 10).await;
+    insert_subscriber(&app, "octavia@example.com", "Octavia Butler", SubscriptionStatus::PendingConfirmation, 5).await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let html_page = get_subscribers_html(&app, "").await;
+
+    // assert
+    let octavia = html_page.find("octavia@example.com</a></td><td>Octavia Butler</td><td>pending_confirmation</td>").unwrap();
+    let ursula = html_page.find("ursula@example.com</a></td><td>Ursula Le Guin</td><td>confirmed</td>").unwrap();
+    assert!(octavia < ursula);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_or_name_prefix() {
+    // arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula Le Guin", SubscriptionStatus::Confirmed, 3).await;
+    insert_subscriber(&app, "butler@example.com", "Octavia Butler", SubscriptionStatus::Confirmed, 2).await;
+    insert_subscriber(&app, "100%_real@example.com", "Ted Chiang", SubscriptionStatus::Confirmed, 1).await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let by_email = get_subscribers_html(&app, "q=URS").await;
+    let by_name = get_subscribers_html(&app, "q=octavia").await;
+    let not_a_prefix = get_subscribers_html(&app, "q=example").await;
+    let with_wildcards = get_subscribers_html(&app, "q=100%25_").await;
+
+    // assert
+    assert!(by_email.contains("ursula@example.com"));
+    assert!(!by_email.contains("butler@example.com"));
+    assert!(by_name.contains("butler@example.com"));
+    assert!(!by_name.contains("ursula@example.com"));
+    assert!(!not_a_prefix.contains("@example.com</a>"));
+    assert!(with_wildcards.contains("100%_real@example.com"));
+    assert!(!with_wildcards.contains("ursula@example.com"));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered_by_status() {
+    // arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula Le Guin", SubscriptionStatus::Confirmed, 2).await;
+    insert_subscriber(&app, "octavia@example.com", "Octavia Butler", SubscriptionStatus::Unsubscribed, 1).await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let html_page = get_subscribers_html(&app, "status=unsubscribed").await;
+
+    // assert
+    assert!(html_page.contains("octavia@example.com"));
+    assert!(!html_page.contains("ursula@example.com"));
+}
+
+#[tokio::test]
+async fn an_unknown_status_filter_is_rejected_with_a_400() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.get_subscribers("status=bogus").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn pagination_walks_through_every_subscriber_exactly_once() {
+    // arrange
+    let app = spawn_app().await;
+    for i in 0..120 {
+        // Several subscribers share a timestamp, which the cursor must disambiguate.
+        insert_subscriber(&app, &format!("reader{}@example.com", i), "Reader", SubscriptionStatus::Confirmed, i / 7).await;
+    }
+    insert_subscriber(&app, "gone@example.com", "Reader", SubscriptionStatus::Unsubscribed, 0).await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let mut seen = HashSet::new();
+    let mut n_pages = 0;
+    let mut query = Some("q=reader&status=confirmed".to_string());
+    while let Some(current_query) = query {
+        let html_page = get_subscribers_html(&app, &current_query).await;
+        n_pages += 1;
+        for line in html_page.lines().filter(|l| l.contains(">reader")) {
+            assert!(seen.insert(line.to_string()), "{} was listed twice.", line);
+        }
+        assert!(!html_page.contains("gone@example.com"));
+        query = next_page_query(&html_page);
+    }
+
+    // assert
+    assert_eq!(seen.len(), 120);
+    assert_eq!(n_pages, 3);
+}
@@ -0,0 +1,172 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use rust2prod::domain::SubscriptionStatus;
+
+async fn insert_subscriber(app: &TestApp, email: &str, status: SubscriptionStatus, subscribed_at: &str, attributes: serde_json::Value) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, status, subscribed_at, attributes)
+        VALUES ($1, $2, 'A subscriber', $3, $4::text::timestamptz, $5)
+        "#,
+        Uuid::new_v4(),
+        email,
+        status as SubscriptionStatus,
+        subscribed_at,
+        attributes
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert subscriber.");
+}
+
+async fn get_export(app: &TestApp, query: &str) -> String {
+    let response = app.get_subscribers_export(query).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.text().await.unwrap()
+}
+
+fn ndjson_emails(body: &str) -> Vec<String> {
+    body.lines()
+        .map(|line| {
+            let subscriber: serde_json::Value = serde_json::from_str(line).unwrap();
+            subscriber["email"].as_str().unwrap().to_owned()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscribers() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.get_subscribers_export("format=csv").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv_with_their_attributes() {
+    // arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", SubscriptionStatus::Confirmed, "2026-01-01T10:00:00Z", serde_json::json!({"company": "Earthsea, Inc."})).await;
+    insert_subscriber(&app, "octavia@example.com", SubscriptionStatus::PendingConfirmation, "2026-01-02T10:00:00Z", serde_json::json!({})).await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.get_subscribers_export("format=csv").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Type"].to_str().unwrap().starts_with("text/csv"));
+    assert!(response.headers()["Content-Disposition"].to_str().unwrap().contains("subscribers.csv"));
+    let body = response.text().await.unwrap();
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    assert_eq!(
+        reader.headers().unwrap(),
+        vec!["id", "email", "name", "status", "subscribed_at", "attributes"]
+    );
+    let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(&records[0][1], "ursula@example.com");
+    assert_eq!(&records[0][3], "confirmed");
+    assert_eq!(&records[0][4], "2026-01-01T10:00:00.000000Z");
+    let attributes: serde_json::Value = serde_json::from_str(&records[0][5]).unwrap();
+    assert_eq!(attributes, serde_json::json!({"company": "Earthsea, Inc."}));
+    assert_eq!(&records[1][1], "octavia@example.com");
+    assert_eq!(&records[1][3], "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_one_json_object_per_line() {
+    // arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", SubscriptionStatus::Confirmed, "2026-01-01T10:00:00Z", serde_json::json!({"plan": "pro"})).await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.get_subscribers_export("format=ndjson").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Disposition"].to_str().unwrap().contains("subscribers.ndjson"));
+    let body = response.text().await.unwrap();
+    let subscriber: serde_json::Value = serde_json::from_str(body.trim_end()).unwrap();
+    assert_eq!(subscriber["email"], "ursula@example.com");
+    assert_eq!(subscriber["status"], "confirmed");
+    assert_eq!(subscriber["subscribed_at"], "2026-01-01T10:00:00.000000Z");
+    assert_eq!(subscriber["attributes"], serde_json::json!({"plan": "pro"}));
+}
+
+#[tokio::test]
+async fn the_export_can_be_filtered_by_status_and_subscription_date() {
+    // arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "before@example.com", SubscriptionStatus::Confirmed, "2025-12-31T23:59:59Z", serde_json::json!({})).await;
+    insert_subscriber(&app, "first-day@example.com", SubscriptionStatus::Confirmed, "2026-01-01T00:00:00Z", serde_json::json!({})).await;
+    insert_subscriber(&app, "pending@example.com", SubscriptionStatus::PendingConfirmation, "2026-01-15T12:00:00Z", serde_json::json!({})).await;
+    insert_subscriber(&app, "last-day@example.com", SubscriptionStatus::Confirmed, "2026-01-31T23:59:59Z", serde_json::json!({})).await;
+    insert_subscriber(&app, "after@example.com", SubscriptionStatus::Confirmed, "2026-02-01T00:00:00Z", serde_json::json!({})).await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let in_january = get_export(&app, "format=ndjson&subscribed_from=2026-01-01&subscribed_until=2026-01-31").await;
+    let confirmed_in_january = get_export(
+        &app,
+        "format=ndjson&status=confirmed&subscribed_from=2026-01-01&subscribed_until=2026-01-31"
+    ).await;
+
+    // assert
+    assert_eq!(ndjson_emails(&in_january), vec!["first-day@example.com", "pending@example.com", "last-day@example.com"]);
+    assert_eq!(ndjson_emails(&confirmed_in_january), vec!["first-day@example.com", "last-day@example.com"]);
+}
+
+#[tokio::test]
+async fn every_subscriber_is_exported_when_there_are_several_batches() {
+    // arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, status, subscribed_at)
+        SELECT gen_random_uuid(), 'subscriber' || n || '@example.com', 'A subscriber', 'confirmed',
+            '2026-01-01T00:00:00Z'::timestamptz + make_interval(secs => n / 3)
+        FROM generate_series(1, 1234) n
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.post_login_with_test_user().await;
+
+    // act
+    let body = get_export(&app, "format=csv").await;
+
+    // assert
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    let emails: Vec<String> = reader.records().map(|r| r.unwrap()[1].to_owned()).collect();
+    assert_eq!(emails.len(), 1234);
+    let unique_emails: std::collections::HashSet<_> = emails.iter().collect();
+    assert_eq!(unique_emails.len(), 1234);
+}
+
+#[tokio::test]
+async fn invalid_export_filters_are_rejected_with_a_400() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+    let test_cases = vec![
+        ("format=xml", "unknown format"),
+        ("format=csv&status=bogus", "unknown status"),
+        ("format=csv&subscribed_from=01/01/2026", "malformed start date"),
+        ("format=csv&subscribed_until=2026-02-30", "impossible end date")
+    ];
+
+    for (query, description) in test_cases {
+        // act
+        let response = app.get_subscribers_export(query).await;
+
+        // assert
+        assert_eq!(response.status().as_u16(), 400, "The export did not fail with a 400 for an {}.", description);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use rust2prod::domain::SubscriptionStatus;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.post_import_subscribers("email,name\nursula@example.com,Ursula", "confirmed").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn importing_as_confirmed_stores_confirmed_subscribers_without_sending_emails() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let csv = "email,name,company\n\
+        ursula@example.com,Ursula Le Guin,Earthsea Press\n\
+        octavia@example.com,Octavia Butler,";
+
+    // act
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Imported 2 subscribers"));
+    let saved = sqlx::query!(
+        r#"
+        SELECT
+            s.email,
+            s.status AS "status: SubscriptionStatus",
+            s.attributes,
+            m.status AS "membership_status: SubscriptionStatus"
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        ORDER BY s.email
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].email, "octavia@example.com");
+    assert_eq!(saved[0].attributes, serde_json::json!({}));
+    assert_eq!(saved[1].attributes, serde_json::json!({ "company": "Earthsea Press" }));
+    for subscriber in saved {
+        assert_eq!(subscriber.status, SubscriptionStatus::Confirmed);
+        assert_eq!(subscriber.membership_status, SubscriptionStatus::Confirmed);
+    }
+}
+
+#[tokio::test]
+async fn importing_with_double_opt_in_sends_a_working_confirmation_email_per_row() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let csv = "email,name\nursula@example.com,Ursula Le Guin\noctavia@example.com,Octavia Butler";
+
+    // act
+    let response = app.post_import_subscribers(csv, "double_opt_in").await;
+
+    // assert
+    assert!(response.text().await.unwrap().contains("Imported 2 subscribers"));
+    // The emails are left to the background worker, not sent during the request.
+    assert!(app.email_server.received_requests().await.unwrap().is_empty());
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    app.confirm_with_link(&confirmation_links.html)
+        .await
+        .error_for_status()
+        .unwrap();
+    let statuses = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions ORDER BY status"#)
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(statuses[0].status, SubscriptionStatus::PendingConfirmation);
+    assert_eq!(statuses[1].status, SubscriptionStatus::Confirmed);
+}
+
+#[tokio::test]
+async fn invalid_rows_are_reported_while_valid_rows_are_imported() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+    let csv = "email,name\n\
+        ursula@example.com,Ursula Le Guin\n\
+        definitely-not-an-email,Octavia Butler\n\
+        ted@example.com,\n\
+        too,many,columns";
+
+    // act
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+
+    // assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Imported 1 subscribers, skipped 0 rows, 3 rows had errors."));
+    assert!(html_page.contains("<li>Line 3: definitely-not-an-email is not a valid subscriber email.</li>"));
+    assert!(html_page.contains("<li>Line 4:  is not a valid subscriber name.</li>"));
+    assert!(html_page.contains("<li>Line 5: "));
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 1);
+}
+
+#[tokio::test]
+async fn duplicates_and_existing_subscribers_are_skipped() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+    app.post_import_subscribers("email,name\nursula@example.com,Ursula Le Guin", "confirmed").await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let csv = "email,name\n\
+        octavia@example.com,Octavia Butler\n\
+        ursula@example.com,Ursula K. Le Guin\n\
+        octavia@example.com,Octavia E. Butler";
+
+    // act
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+
+    // assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Imported 1 subscribers, skipped 2 rows, 0 rows had errors."));
+    assert!(html_page.contains("<li>Line 3: ursula@example.com is already a subscriber.</li>"));
+    assert!(html_page.contains("<li>Line 4: octavia@example.com already appears on line 2.</li>"));
+    let saved = sqlx::query!(r#"SELECT email, name, status AS "status: SubscriptionStatus" FROM subscriptions ORDER BY email"#)
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved[0].name, "Octavia Butler");
+    assert_eq!(saved[1].name, "Ursula Le Guin");
+    assert_eq!(saved[1].status, SubscriptionStatus::Unsubscribed);
+}
+
+#[tokio::test]
+async fn addresses_of_the_same_mailbox_are_imported_once() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+    let csv = "email,name\n\
+        ursula.le.guin@gmail.com,Ursula Le Guin\n\
+        UrsulaLeGuin+news@Gmail.com,Ursula K. Le Guin";
+
+    // act
+    let response = app.post_import_subscribers(csv, "confirmed").await;
+
+    // assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Imported 1 subscribers, skipped 1 rows, 0 rows had errors."));
+    assert!(html_page.contains("<li>Line 3: UrsulaLeGuin+news@gmail.com already appears on line 2.</li>"));
+}
+
+#[tokio::test]
+async fn erased_subscribers_are_not_imported_again() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+    app.post_import_subscribers("email,name\nursula@example.com,Ursula Le Guin", "confirmed").await;
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_subscriber_action(subscriber.id, "delete", &()).await;
+
+    // act
+    let response = app.post_import_subscribers(
+        "email,name\nURSULA@example.com,Ursula Le Guin\noctavia@example.com,Octavia Butler",
+        "confirmed"
+    ).await;
+
+    // assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Imported 1 subscribers, skipped 1 rows, 0 rows had errors."));
+    assert!(html_page.contains(
+        "<li>Line 2: URSULA@example.com was erased at the owner&#x27;s request and cannot be imported.</li>"
+    ));
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "octavia@example.com");
+}
+
+#[tokio::test]
+async fn erased_subscribers_are_not_imported_again_under_an_alias() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+    app.post_import_subscribers("email,name\nursula@gmail.com,Ursula Le Guin", "confirmed").await;
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    app.post_subscriber_action(subscriber.id, "delete", &()).await;
+
+    // act
+    let response = app.post_import_subscribers("email,name\nu.rsula+x@gmail.com,Ursula Le Guin", "confirmed").await;
+
+    // assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Imported 0 subscribers, skipped 1 rows, 0 rows had errors."));
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn a_subscriber_erased_before_the_worker_ran_gets_no_confirmation_email() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+    app.post_import_subscribers("email,name\nursula@example.com,Ursula Le Guin", "double_opt_in").await;
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app.post_subscriber_action(subscriber.id, "delete", &()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+}
+
+#[tokio::test]
+async fn a_csv_without_the_required_columns_is_rejected() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.post_import_subscribers("mail,name\nursula@example.com,Ursula", "confirmed").await;
+
+    // assert
+    assert!(response.text().await.unwrap().contains("The CSV header must contain an `email` column."));
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+}
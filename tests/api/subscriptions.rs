@@ -1,6 +1,11 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with, FakeResolver, TestApp};
+use chrono::{Duration, Utc};
+use rust2prod::domain::SubscribeChallenge;
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use rust2prod::domain::SubscriptionStatus;
 
 #[tokio::test]
 async fn subscribe_returns_a_200_for_valid_form_data() {
@@ -35,14 +40,14 @@ async fn subscribe_persists_the_new_subscriber() {
     app.post_subscriptions(body.into()).await;
 
     // assert
-    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
+    let saved = sqlx::query!(r#"SELECT email, name, status AS "status: SubscriptionStatus" FROM subscriptions"#)
         .fetch_one(&app.db_pool)
         .await
         .expect("Failed to fetch saved subscription.");
 
     assert_eq!(saved.email, "ursula_le_guin@gmail.com");
     assert_eq!(saved.name, "le guin");
-    assert_eq!(saved.status.unwrap(), "pending_confirmation");
+    assert_eq!(saved.status, SubscriptionStatus::PendingConfirmation);
 }
 
 #[tokio::test]
@@ -111,7 +116,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
     // assert
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
 
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
@@ -133,3 +138,1109 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_resends_a_fresh_confirmation_email() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let first_response = app.post_subscriptions(body.into()).await;
+    let second_response = app.post_subscriptions(body.into()).await;
+
+    // assert
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_links = app.get_confirmation_links(&email_requests[0]);
+    let second_links = app.get_confirmation_links(&email_requests[1]);
+    assert_ne!(first_links.html, second_links.html);
+
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn subscribing_with_a_confirmed_email_returns_200_without_sending_an_email() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    app.confirm_with_link(&confirmation_links.html)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+    // Mock verifies on Drop that only the first confirmation email was sent
+}
+
+#[tokio::test]
+async fn an_unsubscribed_email_can_subscribe_again() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn a_bounced_email_goes_through_double_opt_in_again() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'bounced'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn an_email_that_complained_is_not_emailed_when_subscribing_again() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'complained'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::Complained);
+}
+
+#[tokio::test]
+async fn subscribe_only_stores_a_hash_of_the_confirmation_token() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.post_subscriptions(body.into()).await;
+
+    // assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let token = confirmation_links.html
+        .query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned();
+    let saved = sqlx::query!("SELECT subscription_token_hash FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved token.");
+    assert_ne!(saved.subscription_token_hash, token);
+    assert_eq!(saved.subscription_token_hash.len(), 64);
+}
+
+#[tokio::test]
+async fn subscribe_persists_custom_attributes() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&attributes.company=Earthsea%20Press";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT attributes FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.attributes, serde_json::json!({ "company": "Earthsea Press" }));
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_invalid_attribute_names() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&attributes.Company%20Name=Earthsea";
+
+    // act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribe_records_how_consent_was_given() {
+    // arrange
+    let app = spawn_app_with(|c| c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()]).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&source=footer&consent_text_version=2026-10";
+
+    // act
+    let response = app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "Mozilla/5.0 (consent test)")
+        .header("X-Forwarded-For", "203.0.113.7")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let event = sqlx::query!(
+        "SELECT event, source, ip_address, user_agent, consent_text_version FROM consent_events"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the consent event.");
+    assert_eq!(event.event, "subscribed");
+    assert_eq!(event.source, "footer");
+    assert_eq!(event.ip_address.as_deref(), Some("203.0.113.7"));
+    assert_eq!(event.user_agent.as_deref(), Some("Mozilla/5.0 (consent test)"));
+    assert_eq!(event.consent_text_version.as_deref(), Some("2026-10"));
+}
+
+#[tokio::test]
+async fn a_forged_forwarded_header_is_not_recorded_as_consent_evidence() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", "203.0.113.7")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let event = sqlx::query!("SELECT ip_address FROM consent_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the consent event.");
+    assert_eq!(event.ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn subscribe_records_a_default_source_when_the_form_does_not_name_one() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+
+    // assert
+    let event = sqlx::query!("SELECT source, ip_address, consent_text_version FROM consent_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the consent event.");
+    assert_eq!(event.source, "subscription_form");
+    assert_eq!(event.ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(event.consent_text_version, None);
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_an_invalid_consent_label() {
+    // arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (format!("source={}", "a".repeat(65)), "a source that is too long"),
+        ("consent_text_version=v1%0Av2".to_string(), "a version with a line break"),
+    ];
+
+    for (label, description) in test_cases {
+        // act
+        let body = format!("name=le%20guin&email=ursula_le_guin%40gmail.com&{}", label);
+        let response = app.post_subscriptions(body).await;
+
+        // assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not return a 400 Bad Request for {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn consent_events_cannot_be_rewritten() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+
+    // act
+    let result = sqlx::query!("UPDATE consent_events SET source = 'somewhere else'")
+        .execute(&app.db_pool)
+        .await;
+
+    // assert
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn subscribe_accepts_json_and_answers_in_json() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "list_ids": [app.default_list_id],
+        "attributes": { "company": "Earthsea" },
+        "source": "landing-page"
+    });
+
+    // act
+    let response = app.post_subscriptions_json(&body).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let response_body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(response_body, serde_json::json!({ "status": "accepted" }));
+    let saved = sqlx::query!(r#"SELECT email, name, status AS "status: SubscriptionStatus", attributes FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.status, SubscriptionStatus::PendingConfirmation);
+    assert_eq!(saved.attributes, serde_json::json!({ "company": "Earthsea" }));
+    let event = sqlx::query!("SELECT source FROM consent_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the consent event.");
+    assert_eq!(event.source, "landing-page");
+}
+
+#[tokio::test]
+async fn subscribe_reports_every_invalid_json_field() {
+    // arrange
+    let app = spawn_app().await;
+    let body = serde_json::json!({
+        "name": "",
+        "email": "definitely-not-an-email",
+        "source": "x".repeat(65)
+    });
+
+    // act
+    let response = app.post_subscriptions_json(&body).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    let response_body: serde_json::Value = response.json().await.unwrap();
+    let fields = response_body["fields"].as_object().unwrap();
+    let mut invalid_fields: Vec<&str> = fields.keys().map(String::as_str).collect();
+    invalid_fields.sort_unstable();
+    assert_eq!(invalid_fields, vec!["email", "name", "source"]);
+    assert!(fields["email"].as_str().unwrap().contains("definitely-not-an-email"));
+    assert!(response_body["error"].is_string());
+}
+
+#[tokio::test]
+async fn subscribe_reports_missing_json_fields_per_field() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.post_subscriptions_json(&serde_json::json!({ "name": "le guin" })).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    let response_body: serde_json::Value = response.json().await.unwrap();
+    assert!(response_body["fields"]["email"].is_string());
+    assert!(response_body["fields"].get("name").is_none());
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_json_400_for_a_malformed_json_body() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/json")
+        .body(r#"{"name": "le guin", "#)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    let response_body: serde_json::Value = response.json().await.unwrap();
+    assert!(response_body["error"].is_string());
+    assert!(response_body["fields"].is_null());
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_415_for_other_content_types() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    for content_type in [Some("text/plain"), Some("multipart/form-data; boundary=x"), None] {
+        let mut request = app.api_client.post(format!("{}/subscriptions", &app.address));
+        if let Some(content_type) = content_type {
+            request = request.header("Content-Type", content_type);
+        }
+
+        // act
+        let response = request.body(body).send().await.expect("Failed to execute request.");
+
+        // assert
+        assert_eq!(
+            response.status().as_u16(),
+            415,
+            "The API did not return a 415 Unsupported Media Type for content type {:?}.",
+            content_type
+        );
+    }
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn form_submissions_can_ask_for_json_errors() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .body("name=&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    let response_body: serde_json::Value = response.json().await.unwrap();
+    assert!(response_body["fields"]["name"].is_string());
+}
+
+#[tokio::test]
+async fn repeated_subscriptions_for_the_same_address_are_throttled() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // The test app allows 3 requests per address.
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    for _ in 0..3 {
+        let response = app.post_subscriptions(body.into()).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"].to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 0 && retry_after <= 3600);
+}
+
+#[tokio::test]
+async fn a_client_sending_too_many_subscriptions_is_throttled() {
+    // arrange
+    // The test client stands in for a load balancer that forwards other clients.
+    let app = spawn_app_with(|c| c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()]).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // The test app allows 20 requests per IP address; another client gets through.
+        .expect(21)
+        .mount(&app.email_server)
+        .await;
+    for i in 0..20 {
+        let body = format!("name=le%20guin&email=ursula{}%40gmail.com", i);
+        let response = app.post_subscriptions(body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // act
+    let throttled = app.post_subscriptions("name=le%20guin&email=another%40gmail.com".into()).await;
+    let other_client = app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", "203.0.113.7")
+        .body("name=le%20guin&email=another%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // assert
+    assert_eq!(throttled.status().as_u16(), 429);
+    assert!(throttled.headers().get("Retry-After").is_some());
+    assert_eq!(other_client.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_client_cannot_dodge_the_limit_by_forging_forwarded_headers() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // The test app allows 20 requests per IP address.
+        .expect(20)
+        .mount(&app.email_server)
+        .await;
+    let post_from = |i: usize| app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", format!("203.0.113.{}", i))
+        .body(format!("name=le%20guin&email=ursula{}%40gmail.com", i))
+        .send();
+    for i in 0..20 {
+        let response = post_from(i).await.expect("Failed to execute request.");
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // act
+    let response = post_from(20).await.expect("Failed to execute request.");
+
+    // assert
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn throttled_json_requests_get_a_json_error() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com"
+    });
+    for _ in 0..3 {
+        app.post_subscriptions_json(&body).await;
+    }
+
+    // act
+    let response = app.post_subscriptions_json(&body).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().get("Retry-After").is_some());
+    let response_body: serde_json::Value = response.json().await.unwrap();
+    assert!(response_body["error"].is_string());
+}
+
+fn a_challenge_issued_a_minute_ago(app: &TestApp) -> String {
+    let issued_at = Utc::now() - Duration::minutes(1);
+    SubscribeChallenge::issue(issued_at, &app.hmac_secret.0).as_ref().to_owned()
+}
+
+async fn assert_no_subscriber_was_stored(app: &TestApp) {
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn a_filled_in_honeypot_is_answered_like_a_signup_but_sends_no_email() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&website=https%3A%2F%2Fspam.example.com".into())
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_no_subscriber_was_stored(&app).await;
+}
+
+#[tokio::test]
+async fn a_form_submitted_right_after_it_was_served_is_rejected() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issued = app.get_subscribe_challenge().await;
+
+    // act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "challenge": issued["challenge"]
+        }))
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_no_subscriber_was_stored(&app).await;
+}
+
+#[tokio::test]
+async fn a_valid_challenge_is_accepted() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "challenge": a_challenge_issued_a_minute_ago(&app)
+        }))
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_forged_challenge_is_rejected() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let issued_at = Utc::now() - Duration::minutes(1);
+    let forged = SubscribeChallenge::issue(issued_at, &Secret::new("not-our-secret".to_string()));
+
+    // act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "challenge": forged.as_ref()
+        }))
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_no_subscriber_was_stored(&app).await;
+}
+
+#[tokio::test]
+async fn an_expired_challenge_asks_the_visitor_to_reload_the_form() {
+    // arrange
+    let app = spawn_app().await;
+    let issued_at = Utc::now() - Duration::days(2);
+    let challenge = SubscribeChallenge::issue(issued_at, &app.hmac_secret.0);
+
+    // act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "challenge": challenge.as_ref()
+        }))
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_no_subscriber_was_stored(&app).await;
+}
+
+#[tokio::test]
+async fn a_missing_challenge_is_rejected_when_one_is_required() {
+    // arrange
+    let app = spawn_app_with(|c| c.bot_protection.require_challenge = true).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_no_subscriber_was_stored(&app).await;
+}
+
+#[tokio::test]
+async fn the_proof_of_work_is_checked_when_enabled() {
+    // arrange
+    let app = spawn_app_with(|c| c.bot_protection.proof_of_work_difficulty = 8).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    assert_eq!(app.get_subscribe_challenge().await["difficulty"], 8);
+    let challenge = a_challenge_issued_a_minute_ago(&app);
+    let hash = |nonce: &u64| Sha256::digest(format!("{}:ursula_le_guin@gmail.com:{}", challenge, nonce).as_bytes());
+    let solution = (0u64..).find(|nonce| hash(nonce)[0] == 0).unwrap();
+    let wrong_answer = (0u64..).find(|nonce| hash(nonce)[0] != 0).unwrap();
+
+    // act
+    let without_work = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "challenge": challenge,
+            "nonce": wrong_answer.to_string()
+        }))
+        .await;
+    let with_work = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "challenge": challenge,
+            "nonce": solution.to_string()
+        }))
+        .await;
+
+    // assert
+    assert_eq!(without_work.status().as_u16(), 400);
+    assert_eq!(with_work.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+}
+
+#[tokio::test]
+async fn json_clients_can_subscribe_by_solving_the_required_challenge() {
+    // arrange
+    let app = spawn_app_with(|c| {
+        c.bot_protection.require_challenge = true;
+        c.bot_protection.min_submit_seconds = 0;
+        c.bot_protection.proof_of_work_difficulty = 8;
+    }).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let without_challenge = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com"
+        }))
+        .await;
+
+    // act
+    let issued = app.get_subscribe_challenge().await;
+    let challenge = issued["challenge"].as_str().unwrap();
+    let hash = |nonce: &u64| Sha256::digest(format!("{}:ursula_le_guin@gmail.com:{}", challenge, nonce).as_bytes());
+    let solution = (0u64..).find(|nonce| hash(nonce)[0] == 0).unwrap();
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "challenge": challenge,
+            "nonce": solution.to_string()
+        }))
+        .await;
+
+    // assert
+    assert_eq!(without_challenge.status().as_u16(), 400);
+    let error_body: serde_json::Value = without_challenge.json().await.unwrap();
+    assert!(error_body["error"].as_str().unwrap().contains("challenge"));
+    assert_eq!(issued["difficulty"], 8);
+    assert_eq!(response.status().as_u16(), 200);
+    let response_body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(response_body, serde_json::json!({ "status": "accepted" }));
+}
+
+#[tokio::test]
+async fn a_proof_of_work_cannot_be_replayed_for_another_address() {
+    // arrange
+    let app = spawn_app_with(|c| c.bot_protection.proof_of_work_difficulty = 8).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let challenge = a_challenge_issued_a_minute_ago(&app);
+    let hash = |email: &str, nonce: &u64| Sha256::digest(format!("{}:{}:{}", challenge, email, nonce).as_bytes());
+    // Solves the challenge for the first address only.
+    let solution = (0u64..)
+        .find(|nonce| hash("ursula_le_guin@gmail.com", nonce)[0] == 0 && hash("another@gmail.com", nonce)[0] != 0)
+        .unwrap();
+    let signup = |email: &str| serde_json::json!({
+        "name": "le guin",
+        "email": email,
+        "challenge": challenge,
+        "nonce": solution.to_string()
+    });
+
+    // act
+    let first = app.post_subscriptions_json(&signup("ursula_le_guin@gmail.com")).await;
+    let replayed = app.post_subscriptions_json(&signup("another@gmail.com")).await;
+
+    // assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(replayed.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_domain_of_an_address_is_stored_in_lower_case() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40Example.COM".into()).await;
+
+    // assert
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "Ursula_Le_Guin@example.com");
+}
+
+#[tokio::test]
+async fn addresses_of_the_same_mailbox_are_one_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    for email in ["ursula.le.guin%40gmail.com", "UrsulaLeGuin%2Bnews%40Gmail.com", "URSULA.LE.GUIN%40gmail.com"] {
+        let response = app.post_subscriptions(format!("name=le%20guin&email={}", email)).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // assert
+    let saved = sqlx::query!("SELECT email, canonical_email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "ursula.le.guin@gmail.com");
+    assert_eq!(saved[0].canonical_email.as_deref(), Some("ursulaleguin@gmail.com"));
+}
+
+#[tokio::test]
+async fn subscribe_rejects_disposable_addresses() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula@mailinator.com"
+        }))
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    let response_body: serde_json::Value = response.json().await.unwrap();
+    assert!(response_body["fields"]["email"].as_str().unwrap().contains("disposable"));
+}
+
+#[tokio::test]
+async fn internationalized_addresses_are_stored_in_both_forms() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    for email in ["josé@Bücher.de", "josé@xn--bcher-kva.de"] {
+        let response = app
+            .post_subscriptions_json(&serde_json::json!({ "name": "josé", "email": email }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // assert
+    let saved = sqlx::query!("SELECT email, punycode_email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "josé@bücher.de");
+    assert_eq!(saved[0].punycode_email.as_deref(), Some("josé@xn--bcher-kva.de"));
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "josé@xn--bcher-kva.de");
+}
+
+async fn spawn_app_with_mx_check(resolver: &FakeResolver, fail_open: bool) -> TestApp {
+    let app = spawn_app_with(|c| {
+        c.mx_check.enabled = true;
+        c.mx_check.resolver = resolver.address.clone();
+        c.mx_check.timeout_milliseconds = 200;
+        c.mx_check.fail_open = fail_open;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app
+}
+
+async fn subscribe_with_email(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_subscriptions_json(&serde_json::json!({ "name": "le guin", "email": email }))
+        .await
+}
+
+#[tokio::test]
+async fn subscribe_rejects_addresses_at_domains_that_do_not_accept_email() {
+    // arrange
+    let resolver = FakeResolver::start().await;
+    let app = spawn_app_with_mx_check(&resolver, true).await;
+
+    // act
+    let response = subscribe_with_email(&app, "ursula@gmial.com").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    let response_body: serde_json::Value = response.json().await.unwrap();
+    assert!(response_body["fields"]["email"].as_str().unwrap().contains("gmial.com"));
+    assert!(app.email_server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn subscribe_accepts_domains_with_an_mx_or_an_a_record() {
+    // arrange
+    let resolver = FakeResolver::start().await;
+    let app = spawn_app_with_mx_check(&resolver, false).await;
+
+    // act
+    let with_mx = subscribe_with_email(&app, "ursula@example.com").await;
+    let with_a_only = subscribe_with_email(&app, "ursula@a-record-only.example").await;
+
+    // assert
+    assert_eq!(with_mx.status().as_u16(), 200);
+    assert_eq!(with_a_only.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_unresponsive_resolver_lets_subscriptions_through_when_failing_open() {
+    // arrange
+    let resolver = FakeResolver::start().await;
+    let app = spawn_app_with_mx_check(&resolver, true).await;
+
+    // act
+    let response = subscribe_with_email(&app, "ursula@unresponsive.example").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_truncated_answer_is_a_failed_lookup_rather_than_a_missing_mail_server() {
+    // arrange
+    let resolver = FakeResolver::start().await;
+    let app = spawn_app_with_mx_check(&resolver, true).await;
+
+    // act
+    let response = subscribe_with_email(&app, "ursula@truncated.example").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn an_unresponsive_resolver_rejects_subscriptions_when_failing_closed() {
+    // arrange
+    let resolver = FakeResolver::start().await;
+    let app = spawn_app_with_mx_check(&resolver, false).await;
+
+    // act
+    let response = subscribe_with_email(&app, "ursula@unresponsive.example").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    let response_body: serde_json::Value = response.json().await.unwrap();
+    assert!(response_body["fields"]["email"].as_str().unwrap().contains("try again later"));
+    assert!(app.email_server.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn mx_check_answers_are_cached_per_domain() {
+    // arrange
+    let resolver = FakeResolver::start().await;
+    let app = spawn_app_with_mx_check(&resolver, false).await;
+
+    // act
+    subscribe_with_email(&app, "ursula@example.com").await;
+    subscribe_with_email(&app, "octavia@example.com").await;
+    subscribe_with_email(&app, "ursula@gmial.com").await;
+    subscribe_with_email(&app, "octavia@gmial.com").await;
+
+    // assert
+    // One MX query for each domain: the second lookups come from the cache.
+    assert_eq!(resolver.query_count(), 2);
+}
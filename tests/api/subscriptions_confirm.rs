@@ -1,107 +1,368 @@
-use wiremock::{Mock, ResponseTemplate};
-use wiremock::matchers::{method, path};
-use rust2prod::routes::generate_subscription_token;
-use crate::helpers::spawn_app;
-
-#[tokio::test]
-async fn confirmations_without_token_are_rejected_with_a_400() {
-    // arrange
-    let app = spawn_app().await;
-
-    // act
-    let response = reqwest::get(&format!("{}/subscriptions/confirm", app.address))
-        .await
-        .unwrap();
-
-    // assert
-    assert_eq!(response.status().as_u16(), 400);
-}
-
-#[tokio::test]
-async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
-    // arrange
-    let app = spawn_app().await;
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
-    Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .mount(&app.email_server)
-        .await;
-
-    app.post_subscriptions(body.into()).await;
-    let email_request = &app.email_server.received_requests().await.unwrap()[0];
-
-    let confirmation_link = app.get_confirmation_links(email_request);
-
-    // act
-    let response = reqwest::get(confirmation_link.html)
-        .await.unwrap();
-
-    // assert
-    assert_eq!(response.status().as_u16(), 200);
-}
-
-#[tokio::test]
-async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
-    // arrange
-    let app = spawn_app().await;
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
-    Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .mount(&app.email_server)
-        .await;
-
-    app.post_subscriptions(body.into()).await;
-
-    let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
-
-    // act
-    reqwest::get(confirmation_links.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-
-    // assert
-    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
-        .fetch_one(&app.db_pool)
-        .await
-        .expect("Failed to fetch saved subscription.");
-
-    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
-    assert_eq!(saved.name, "le guin");
-    assert_eq!(saved.status.unwrap().as_str(), "confirmed");
-}
-
-#[tokio::test]
-async fn confirm_fails_if_there_is_a_fatal_database_error() {
-    // arrange
-    let app = spawn_app().await;
-    let token = generate_subscription_token();
-
-    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscriber_id;")
-        .execute(&app.db_pool)
-        .await
-        .unwrap();
-
-    // act
-    let response = app.confirm(token).await;
-
-    // assert
-    assert_eq!(response.status().as_u16(), 500);
-}
-
-#[tokio::test]
-async fn confirm_fails_if_token_does_not_exist() {
-    // arrange
-    let app = spawn_app().await;
-    let token = generate_subscription_token();
-
-    // act
-    let response = app.confirm(token).await;
-
-    // assert
-    assert_eq!(response.status().as_u16(), 401);
-}
\ No newline at end of file
+use wiremock::{Mock, ResponseTemplate};
+use wiremock::matchers::{method, path};
+use rust2prod::routes::generate_subscription_token;
+use crate::helpers::spawn_app;
+use rust2prod::domain::SubscriptionStatus;
+
+#[tokio::test]
+async fn confirmations_without_token_are_rejected_with_a_400() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = reqwest::get(&format!("{}/subscriptions/confirm", app.address))
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+
+    let confirmation_link = app.get_confirmation_links(email_request);
+
+    // act
+    let response = reqwest::get(confirmation_link.html)
+        .await.unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // act
+    app.confirm_with_link(&confirmation_links.html)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // assert
+    let saved = sqlx::query!(r#"SELECT email, name, status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+}
+
+#[tokio::test]
+async fn confirming_a_subscription_is_recorded_as_a_consent_event() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // act
+    app.confirm_with_link(&confirmation_links.html)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // assert
+    let events = sqlx::query!("SELECT event, source FROM consent_events ORDER BY occurred_at")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch consent events.");
+    let events: Vec<_> = events.into_iter().map(|e| (e.event, e.source)).collect();
+    assert_eq!(events, vec![
+        ("subscribed".to_string(), "subscription_form".to_string()),
+        ("confirmed".to_string(), "confirmation_link".to_string())
+    ]);
+}
+
+#[tokio::test]
+async fn confirm_fails_if_there_is_a_fatal_database_error() {
+    // arrange
+    let app = spawn_app().await;
+    let token = generate_subscription_token();
+
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscriber_id;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = app.confirm(token).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 500);
+}
+
+#[tokio::test]
+async fn confirm_fails_if_token_does_not_exist() {
+    // arrange
+    let app = spawn_app().await;
+    let token = generate_subscription_token();
+
+    // act
+    let response = app.confirm(token).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_confirmation_link_can_only_be_used_once() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // act
+    let first_response = app.confirm_with_link(&confirmation_links.html).await;
+    let second_response = app.confirm_with_link(&confirmation_links.html).await;
+
+    // assert
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_expired_confirmation_link_is_rejected_with_a_410() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '1 year'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 410);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<form action="/subscriptions/confirm/resend" method="post">"#));
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn an_expired_token_can_be_exchanged_for_a_new_confirmation_email() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let expired_token = confirmation_links.html
+        .query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned();
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '1 year'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = app.api_client
+        .post(format!("{}/subscriptions/confirm/resend", app.address))
+        .form(&[("subscription_token", expired_token)])
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let new_confirmation_links = app.get_confirmation_links(email_request);
+    let confirmation_response = app.confirm_with_link(&new_confirmation_links.html).await;
+    assert_eq!(confirmation_response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_token_left_over_once_the_subscriber_moved_on_cannot_resend_a_confirmation_email() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let stale_token = confirmation_links.html
+        .query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned();
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = app.api_client
+        .post(format!("{}/subscriptions/confirm/resend", app.address))
+        .form(&[("subscription_token", stale_token)])
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Unsubscribed);
+}
+
+#[tokio::test]
+async fn following_the_confirmation_link_does_not_confirm_the_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<form action="/subscriptions/confirm" method="post">"#));
+    assert!(html_page.contains("Confirm my subscription"));
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::PendingConfirmation);
+}
+
+#[tokio::test]
+async fn an_unknown_token_renders_an_error_page() {
+    // arrange
+    let app = spawn_app().await;
+    let token = generate_subscription_token();
+
+    // act
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        app.address,
+        token
+    ))
+    .await
+    .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link is invalid or has already been used."));
+}
+
+#[tokio::test]
+async fn a_subscriber_who_unsubscribed_cannot_be_confirmed_with_a_leftover_link() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // act
+    let page_response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    let confirm_response = app.confirm_with_link(&confirmation_links.html).await;
+
+    // assert
+    assert_eq!(page_response.status().as_u16(), 409);
+    let html_page = page_response.text().await.unwrap();
+    assert!(html_page.contains("This subscription can no longer be confirmed."));
+    assert!(!html_page.contains("Confirm my subscription"));
+    assert_eq!(confirm_response.status().as_u16(), 409);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, SubscriptionStatus::Unsubscribed);
+}
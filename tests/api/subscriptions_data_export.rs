@@ -0,0 +1,319 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use chrono::{Duration, Utc};
+use rust2prod::domain::DataExportToken;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscriber_id(app: &TestApp, email: &str) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the subscriber.")
+        .id
+}
+
+/// Ask for an export and return the link that was emailed.
+async fn request_data_export_link(app: &TestApp, email: &str) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app.post_data_export_request(email).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+#[tokio::test]
+async fn a_subscriber_can_download_their_data_through_the_emailed_link() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    sqlx::query!(r#"UPDATE subscriptions SET attributes = '{"company": "Earthsea"}'"#)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let link = request_data_export_link(&app, "ursula@example.com").await;
+
+    // act
+    let response = app.get_data_export(&link).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Disposition"].to_str().unwrap().contains("subscriber-data.json"));
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], "ursula@example.com");
+    assert_eq!(export["subscriber"]["status"], "confirmed");
+    assert_eq!(export["subscriber"]["attributes"], serde_json::json!({"company": "Earthsea"}));
+    assert!(export["consent"]["subscribed_at"].is_string());
+    assert_eq!(export["consent"]["events"][0]["event"], "subscribed");
+    assert_eq!(export["consent"]["events"][1]["event"], "confirmed");
+    assert_eq!(export["lists"][0]["list"], "Newsletter");
+    assert_eq!(export["lists"][0]["status"], "confirmed");
+}
+
+#[tokio::test]
+async fn requesting_an_export_for_an_unknown_address_sends_nothing() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    app.email_server.reset().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let known = app.post_data_export_request("ursula@example.com").await;
+    let unknown = app.post_data_export_request("octavia@example.com").await;
+
+    // assert
+    assert_eq!(unknown.status().as_u16(), 200);
+    assert_eq!(unknown.text().await.unwrap(), known.text().await.unwrap());
+    // Mock verifies on Drop that only the known address got an email
+}
+
+#[tokio::test]
+async fn the_export_link_goes_to_the_stored_address_when_another_spelling_is_typed() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula.le.guin@gmail.com", app.default_list_id).await;
+    app.email_server.reset().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    for alias in ["UrsulaLeGuin+export@gmail.com", "URSULA.LE.GUIN@googlemail.com"] {
+        let response = app.post_data_export_request(alias).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // assert
+    for email_request in app.email_server.received_requests().await.unwrap() {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        assert_eq!(body["To"], "ursula.le.guin@gmail.com");
+    }
+}
+
+#[tokio::test]
+async fn a_failure_to_send_the_export_does_not_reveal_the_address_is_known() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let known = app.post_data_export_request("ursula@example.com").await;
+    let unknown = app.post_data_export_request("octavia@example.com").await;
+
+    // assert
+    assert_eq!(known.status().as_u16(), 200);
+    assert_eq!(known.text().await.unwrap(), unknown.text().await.unwrap());
+}
+
+#[tokio::test]
+async fn repeated_export_requests_for_the_same_address_are_throttled() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // The test app allows 3 requests per address.
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    for _ in 0..3 {
+        let response = app.post_data_export_request("ursula@example.com").await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // act
+    let known = app.post_data_export_request("ursula@example.com").await;
+    for _ in 0..3 {
+        app.post_data_export_request("octavia@example.com").await;
+    }
+    let unknown = app.post_data_export_request("octavia@example.com").await;
+
+    // assert
+    assert_eq!(known.status().as_u16(), 429);
+    assert!(known.headers().get("Retry-After").is_some());
+    assert_eq!(unknown.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn a_client_sending_too_many_export_requests_is_throttled() {
+    // arrange
+    let app = spawn_app().await;
+    for i in 0..20 {
+        let response = app.post_data_export_request(&format!("ursula{}@example.com", i)).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // act
+    let response = app.post_data_export_request("octavia@example.com").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn requesting_an_export_for_an_invalid_address_is_rejected_with_a_400() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.post_data_export_request("not-an-email").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_tampered_download_link_is_rejected_with_a_401() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    app.create_confirmed_subscriber("octavia@example.com", app.default_list_id).await;
+    let mut link = request_data_export_link(&app, "ursula@example.com").await;
+    let someone_else = subscriber_id(&app, "octavia@example.com").await;
+    let query: Vec<(String, String)> = link
+        .query_pairs()
+        .map(|(k, v)| {
+            let v = if k == "subscriber_id" { someone_else.to_string() } else { v.into_owned() };
+            (k.into_owned(), v)
+        })
+        .collect();
+    link.query_pairs_mut().clear().extend_pairs(query);
+
+    // act
+    let response = app.get_data_export(&link).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_expired_download_link_is_rejected_with_a_410() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    let subscriber_id = subscriber_id(&app, "ursula@example.com").await;
+    let expires_at = (Utc::now() - Duration::minutes(1)).timestamp();
+    let token = DataExportToken::generate(subscriber_id, expires_at, &app.hmac_secret.0);
+    let link = reqwest::Url::parse(&format!(
+        "{}/subscriptions/data-export?subscriber_id={}&expires_at={}&token={}",
+        app.address,
+        subscriber_id,
+        expires_at,
+        token.as_ref()
+    ))
+    .unwrap();
+
+    // act
+    let response = app.get_data_export(&link).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[tokio::test]
+async fn the_export_lists_the_issues_delivered_to_the_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    let subscriber_id = subscriber_id(&app, "ursula@example.com").await;
+    app.post_login_with_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Issue #1",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
+    })).await;
+    app.dispatch_all_pending_emails().await;
+
+    // act
+    let response = app.get_subscriber_data(subscriber_id).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    let delivered_issues = export["delivered_issues"].as_array().unwrap();
+    assert_eq!(delivered_issues.len(), 1);
+    assert_eq!(delivered_issues[0]["title"], "Issue #1");
+    assert!(delivered_issues[0]["delivered_at"].is_string());
+    assert_eq!(export["pending_deliveries"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_download_the_data_of_a_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    let subscriber_id = subscriber_id(&app, "ursula@example.com").await;
+
+    // act
+    let response = app.get_subscriber_data(subscriber_id).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn downloading_the_data_of_an_unknown_subscriber_returns_a_404() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.get_subscriber_data(Uuid::new_v4()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn an_admin_can_email_a_subscriber_the_link_to_their_data() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    let subscriber_id = subscriber_id(&app, "ursula@example.com").await;
+    app.post_login_with_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app.post_subscriber_action(subscriber_id, "data-export", &()).await;
+    let html_page = app.get_subscriber_html(subscriber_id).await;
+
+    // assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert!(html_page.contains("The subscriber has been sent a link to download their data."));
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let link = app.get_confirmation_links(&email_request).html;
+    let export: serde_json::Value = app.get_data_export(&link).await.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], "ursula@example.com");
+}
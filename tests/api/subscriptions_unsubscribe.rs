@@ -0,0 +1,197 @@
+use crate::helpers::{spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use rust2prod::domain::SubscriptionStatus;
+
+async fn create_confirmed_subscriber(app: &TestApp) -> Uuid {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    app.confirm_with_link(&confirmation_links.html)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .id
+}
+
+#[tokio::test]
+async fn unsubscribe_without_parameters_is_rejected_with_a_400() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = reqwest::get(&format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unsubscribe_with_an_invalid_token_is_rejected_with_a_401() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let token_for_someone_else = app.unsubscribe_token(Uuid::new_v4());
+
+    // act
+    let get_response = app.get_unsubscribe(subscriber_id, &token_for_someone_else).await;
+    let post_response = app.post_unsubscribe(subscriber_id, &token_for_someone_else).await;
+
+    // assert
+    assert_eq!(get_response.status().as_u16(), 401);
+    assert_eq!(post_response.status().as_u16(), 401);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+}
+
+#[tokio::test]
+async fn the_unsubscribe_link_shows_a_confirmation_page_without_unsubscribing() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let token = app.unsubscribe_token(subscriber_id);
+
+    // act
+    let response = app.get_unsubscribe(subscriber_id, &token).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<form action="/subscriptions/unsubscribe" method="post">"#));
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::Confirmed);
+}
+
+#[tokio::test]
+async fn submitting_the_unsubscribe_form_unsubscribes_a_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let token = app.unsubscribe_token(subscriber_id);
+
+    // act
+    let response = app.post_unsubscribe(subscriber_id, &token).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::Unsubscribed);
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_unsubscribes_a_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let token = app.unsubscribe_token(subscriber_id);
+
+    // act
+    let response = app.post_one_click_unsubscribe(subscriber_id, &token).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::Unsubscribed);
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_with_an_invalid_token_is_rejected_with_a_401() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let token_for_someone_else = app.unsubscribe_token(Uuid::new_v4());
+
+    // act
+    let response = app.post_one_click_unsubscribe(subscriber_id, &token_for_someone_else).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn every_status_change_is_recorded_in_the_status_history() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    let token = app.unsubscribe_token(subscriber_id);
+
+    // act
+    app.post_unsubscribe(subscriber_id, &token).await;
+    app.post_unsubscribe(subscriber_id, &token).await;
+
+    // assert
+    let history = sqlx::query!(
+        r#"
+        SELECT
+            from_status AS "from_status: SubscriptionStatus",
+            to_status AS "to_status: SubscriptionStatus",
+            reason
+        FROM subscription_status_history
+        WHERE subscriber_id = $1
+        ORDER BY changed_at
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch the status history.");
+    let history: Vec<_> = history.into_iter().map(|c| (c.from_status, c.to_status, c.reason)).collect();
+    assert_eq!(
+        history,
+        vec![
+            (None, SubscriptionStatus::PendingConfirmation, "subscription_form".to_string()),
+            (Some(SubscriptionStatus::PendingConfirmation), SubscriptionStatus::Confirmed, "confirmation_link".to_string()),
+            (Some(SubscriptionStatus::Confirmed), SubscriptionStatus::Unsubscribed, "unsubscribe_link".to_string())
+        ]
+    );
+}
+
+#[tokio::test]
+async fn unsubscribing_keeps_the_status_of_a_subscriber_who_complained() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'complained'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let token = app.unsubscribe_token(subscriber_id);
+
+    // act
+    let response = app.post_one_click_unsubscribe(subscriber_id, &token).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(r#"SELECT status AS "status: SubscriptionStatus" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, SubscriptionStatus::Complained);
+}
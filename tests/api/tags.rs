@@ -0,0 +1,211 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn tag_subscriber(app: &TestApp, email: &str, tag: &str) -> Uuid {
+    app.post_tags(&serde_json::json!({ "email": email, "tag": tag }))
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("SELECT tag_id FROM tags WHERE name = $1", tag)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved tag.")
+        .tag_id
+}
+
+async fn recipients_of(app: &TestApp, segment: &str) -> i64 {
+    let response = app.get_newsletter_recipients(segment).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["recipients"].as_i64().unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_tag_subscribers() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.post_tags(&serde_json::json!({
+        "email": "ursula_le_guin@gmail.com",
+        "tag": "beta"
+    })).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_can_tag_a_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("ursula_le_guin@gmail.com", app.default_list_id).await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.post_tags(&serde_json::json!({
+        "email": "ursula_le_guin@gmail.com",
+        "tag": "beta"
+    })).await;
+    let html_page = app.get_tags_html().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/tags");
+    assert!(html_page.contains("<p><i>The subscriber has been tagged.</i></p>"));
+    assert!(html_page.contains("beta (1 subscribers)"));
+}
+
+#[tokio::test]
+async fn tagging_an_unknown_email_is_rejected() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.post_tags(&serde_json::json!({
+        "email": "nobody@example.com",
+        "tag": "beta"
+    })).await;
+    let html_page = app.get_tags_html().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/tags");
+    assert!(html_page.contains("<p><i>There is no subscriber with this email address.</i></p>"));
+    let n_tags = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM tags"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tags, 0);
+}
+
+#[tokio::test]
+async fn the_recipient_count_reflects_the_segment() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("beta@example.com", app.default_list_id).await;
+    app.create_confirmed_subscriber("everyone@example.com", app.default_list_id).await;
+    app.post_login_with_test_user().await;
+    let beta = tag_subscriber(&app, "beta@example.com", "beta").await;
+    let list = format!("list_id={}", app.default_list_id);
+
+    // act
+    let whole_list = recipients_of(&app, &list).await;
+    let included = recipients_of(&app, &format!("{}&include_tag_id={}", list, beta)).await;
+    let excluded = recipients_of(&app, &format!("{}&exclude_tag_id={}", list, beta)).await;
+    let no_list = recipients_of(&app, "").await;
+
+    // assert
+    assert_eq!(whole_list, 2);
+    assert_eq!(included, 1);
+    assert_eq!(excluded, 1);
+    assert_eq!(no_list, 0);
+}
+
+#[tokio::test]
+async fn issues_only_reach_subscribers_matching_the_segment() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("beta@example.com", app.default_list_id).await;
+    app.create_confirmed_subscriber("staff@example.com", app.default_list_id).await;
+    app.create_confirmed_subscriber("everyone@example.com", app.default_list_id).await;
+    app.post_login_with_test_user().await;
+    let beta = tag_subscriber(&app, "beta@example.com", "beta").await;
+    tag_subscriber(&app, "staff@example.com", "beta").await;
+    let staff = tag_subscriber(&app, "staff@example.com", "staff").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = format!(
+        "title=Beta%20news&text_content=Plain&html_content=%3Cp%3EHTML%3C%2Fp%3E\
+        &idempotency_key={}&list_id={}&include_tag_id={}&exclude_tag_id={}",
+        Uuid::new_v4(),
+        app.default_list_id,
+        beta,
+        staff
+    );
+
+    // act
+    let response = app.api_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "beta@example.com");
+}
+
+#[tokio::test]
+async fn the_recipient_count_matches_who_the_issue_is_queued_for() {
+    // arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber("beta@example.com", app.default_list_id).await;
+    app.create_confirmed_subscriber("staff@example.com", app.default_list_id).await;
+    app.create_confirmed_subscriber("everyone@example.com", app.default_list_id).await;
+    app.post_login_with_test_user().await;
+    let beta = tag_subscriber(&app, "beta@example.com", "beta").await;
+    tag_subscriber(&app, "staff@example.com", "beta").await;
+    let staff = tag_subscriber(&app, "staff@example.com", "staff").await;
+    let other_list = app.create_list("Weekly digest").await;
+    app.create_confirmed_subscriber("beta@example.com", other_list).await;
+    let segment = format!(
+        "list_id={}&list_id={}&include_tag_id={}&exclude_tag_id={}",
+        app.default_list_id, other_list, beta, staff
+    );
+
+    // act
+    let count = recipients_of(&app, &segment).await;
+    let response = app.api_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(format!(
+            "title=Beta%20news&text_content=Plain&html_content=%3Cp%3EHTML%3C%2Fp%3E&idempotency_key={}&{}",
+            Uuid::new_v4(),
+            segment
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let queued = sqlx::query!(r#"SELECT COUNT(*) as "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 1);
+    assert_eq!(queued, count);
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_tag_is_rejected_with_a_400() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id,
+        "include_tag_id": Uuid::new_v4()
+    })).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+}
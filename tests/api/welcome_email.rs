@@ -0,0 +1,194 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn welcome_email_form() -> serde_json::Value {
+    serde_json::json!({
+        "enabled": "on",
+        "subject": "Welcome aboard",
+        "html_content": "<p>Hi {{ name }}, welcome!</p>",
+        "text_content": "Hi {{ name }}, welcome!"
+    })
+}
+
+async fn enable_welcome_email(app: &TestApp) {
+    app.post_login_with_test_user().await;
+    let response = app.post_welcome_email(&welcome_email_form()).await;
+    assert_is_redirect_to(&response, "/admin/welcome-email");
+}
+
+async fn queued_welcome_emails(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM welcome_email_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count queued welcome emails.")
+        .count
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_edit_the_welcome_email() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.post_welcome_email(&welcome_email_form()).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_can_edit_the_welcome_email() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+
+    // act
+    let response = app.post_welcome_email(&welcome_email_form()).await;
+    let html_page = app.get_welcome_email_html().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/welcome-email");
+    assert!(html_page.contains("<p><i>The welcome email has been saved.</i></p>"));
+    assert!(html_page.contains(r#"<input type="checkbox" name="enabled" checked>"#));
+    assert!(html_page.contains(r#"value="Welcome aboard""#));
+    assert!(html_page.contains("&lt;p&gt;Hi {{ name }}, welcome!&lt;/p&gt;"));
+}
+
+#[tokio::test]
+async fn a_welcome_email_with_a_broken_template_is_rejected() {
+    // arrange
+    let app = spawn_app().await;
+    app.post_login_with_test_user().await;
+    let mut form = welcome_email_form();
+    form["text_content"] = "Hi {{ name, welcome!".into();
+
+    // act
+    let response = app.post_welcome_email(&form).await;
+    let html_page = app.get_welcome_email_html().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/welcome-email");
+    assert!(html_page.contains("The plain text content is not a valid template"));
+    assert!(!html_page.contains("Welcome aboard"));
+}
+
+#[tokio::test]
+async fn the_welcome_email_is_off_until_an_admin_turns_it_on() {
+    // arrange
+    let app = spawn_app().await;
+    let list_id = app.default_list_id;
+
+    // act
+    app.create_confirmed_subscriber("ursula@example.com", list_id).await;
+
+    // assert
+    assert_eq!(queued_welcome_emails(&app).await, 0);
+}
+
+#[tokio::test]
+async fn confirming_queues_a_welcome_email_for_the_delivery_worker() {
+    // arrange
+    let app = spawn_app().await;
+    enable_welcome_email(&app).await;
+
+    // act
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+
+    // assert
+    // Only the confirmation email has gone out: the welcome email waits for the worker.
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+    assert_eq!(queued_welcome_emails(&app).await, 1);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Welcome aboard");
+    assert!(body["TextBody"].as_str().unwrap().starts_with("Hi le guin, welcome!"));
+    assert!(body["HtmlBody"].as_str().unwrap().contains("/subscriptions/unsubscribe?"));
+    assert_eq!(queued_welcome_emails(&app).await, 0);
+}
+
+#[tokio::test]
+async fn a_subscriber_is_welcomed_only_once() {
+    // arrange
+    let app = spawn_app().await;
+    enable_welcome_email(&app).await;
+    let new_list = app.create_list("Weekly digest").await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    let welcome_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    drop(welcome_guard);
+
+    // act
+    // Joining another list goes through confirmation again.
+    app.create_confirmed_subscriber("ursula@example.com", new_list).await;
+
+    // assert
+    assert_eq!(queued_welcome_emails(&app).await, 0);
+}
+
+#[tokio::test]
+async fn issues_are_still_delivered_when_the_welcome_email_cannot_be_sent() {
+    // arrange
+    let app = spawn_app().await;
+    enable_welcome_email(&app).await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    sqlx::query!("DELETE FROM welcome_email")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let response = app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "list_id": app.default_list_id
+    })).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    // Mock verifies on Drop that the issue went out.
+}
+
+#[tokio::test]
+async fn a_subscriber_who_left_before_the_worker_ran_is_not_welcomed() {
+    // arrange
+    let app = spawn_app().await;
+    enable_welcome_email(&app).await;
+    app.create_confirmed_subscriber("ursula@example.com", app.default_list_id).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    assert_eq!(queued_welcome_emails(&app).await, 0);
+}
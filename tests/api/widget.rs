@@ -0,0 +1,142 @@
+use crate::helpers::spawn_app;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn preflight_from_an_allowed_origin_is_accepted() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.preflight_subscriptions("https://blog.example.com").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(
+        response.headers()["Access-Control-Allow-Origin"],
+        "https://blog.example.com"
+    );
+    assert_eq!(response.headers()["Access-Control-Allow-Methods"], "POST");
+}
+
+#[tokio::test]
+async fn preflight_from_an_unknown_origin_is_rejected() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.preflight_subscriptions("https://evil.example.com").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response.headers().get("Access-Control-Allow-Origin").is_none());
+}
+
+#[tokio::test]
+async fn subscribing_from_an_allowed_origin_attributes_the_subscriber_to_the_site() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        // The embedding page does not get to choose its own attribution.
+        "source": "somewhere_else"
+    });
+
+    // act
+    let response = app.post_subscriptions_json_from("https://blog.example.com", &body).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Access-Control-Allow-Origin"],
+        "https://blog.example.com"
+    );
+    let saved = sqlx::query!("SELECT signup_source FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.signup_source.as_deref(), Some("widget:blog"));
+    let event = sqlx::query!("SELECT source FROM consent_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the consent event.");
+    assert_eq!(event.source, "widget:blog");
+}
+
+#[tokio::test]
+async fn subscribing_from_an_unknown_origin_is_rejected() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com"
+    });
+
+    // act
+    let response = app.post_subscriptions_json_from("https://evil.example.com", &body).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 403);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn subscribing_from_the_application_own_origin_is_not_attributed_to_a_widget() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com"
+    });
+
+    // act
+    let response = app.post_subscriptions_json(&body).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().get("Access-Control-Allow-Origin").is_none());
+    let saved = sqlx::query!("SELECT signup_source FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.signup_source.as_deref(), Some("subscription_form"));
+}
+
+#[tokio::test]
+async fn widget_script_points_at_the_subscription_endpoint() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.get_widget_script().await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let content_type = response.headers()["Content-Type"].to_str().unwrap().to_owned();
+    assert!(content_type.starts_with("application/javascript"));
+    let script = response.text().await.unwrap();
+    assert!(script.contains("/subscriptions\""));
+    assert!(!script.contains("__SUBSCRIBE_ENDPOINT__"));
+}